@@ -2,7 +2,10 @@
 
 pub mod prelude;
 
+pub mod access_key;
+pub mod bucket;
 pub mod config;
 pub mod invalid_jwt;
 pub mod key;
+pub mod object;
 pub mod user;
@@ -1,6 +1,9 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.14
 
+pub use super::access_key::Entity as AccessKey;
+pub use super::bucket::Entity as Bucket;
 pub use super::config::Entity as Config;
 pub use super::invalid_jwt::Entity as InvalidJwt;
 pub use super::key::Entity as Key;
+pub use super::object::Entity as Object;
 pub use super::user::Entity as User;
@@ -4,6 +4,12 @@ mod m20250926_172600_key;
 mod m20250926_172626_invalid_jwt;
 mod m20250926_173213_user;
 mod m20251007_164500_config;
+mod m20251018_120000_object;
+mod m20251019_090000_bucket;
+mod m20251020_100000_access_key;
+mod m20251021_080000_access_key_expires;
+mod m20251022_090000_object_parts;
+mod m20251023_090000_object_checksum;
 
 pub struct Migrator;
 
@@ -15,6 +21,12 @@ impl MigratorTrait for Migrator {
       Box::new(m20250926_172626_invalid_jwt::Migration),
       Box::new(m20250926_173213_user::Migration),
       Box::new(m20251007_164500_config::Migration),
+      Box::new(m20251018_120000_object::Migration),
+      Box::new(m20251019_090000_bucket::Migration),
+      Box::new(m20251020_100000_access_key::Migration),
+      Box::new(m20251021_080000_access_key_expires::Migration),
+      Box::new(m20251022_090000_object_parts::Migration),
+      Box::new(m20251023_090000_object_checksum::Migration),
     ]
   }
 }
@@ -0,0 +1,61 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Object::Table)
+          .if_not_exists()
+          .col(pk_uuid(Object::Id))
+          .col(string(Object::Bucket))
+          .col(string(Object::Key))
+          .col(big_integer(Object::Size))
+          .col(string(Object::Etag))
+          .col(string_null(Object::ContentType))
+          .col(string_null(Object::CacheControl))
+          .col(string_null(Object::ContentDisposition))
+          .col(json_binary(Object::Metadata))
+          .col(date_time(Object::Created))
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_object_bucket_key")
+          .table(Object::Table)
+          .col(Object::Bucket)
+          .col(Object::Key)
+          .unique()
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Object::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(DeriveIden)]
+enum Object {
+  Table,
+  Id,
+  Bucket,
+  Key,
+  Size,
+  Etag,
+  ContentType,
+  CacheControl,
+  ContentDisposition,
+  Metadata,
+  Created,
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Bucket::Table)
+          .if_not_exists()
+          .col(pk_uuid(Bucket::Id))
+          .col(string_uniq(Bucket::Name))
+          .col(uuid_null(Bucket::Owner))
+          .col(string(Bucket::Region))
+          .col(json_binary(Bucket::Settings))
+          .col(date_time(Bucket::Created))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(Bucket::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(DeriveIden)]
+enum Bucket {
+  Table,
+  Id,
+  Name,
+  Owner,
+  Region,
+  Settings,
+  Created,
+}
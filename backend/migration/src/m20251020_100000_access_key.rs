@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(AccessKey::Table)
+          .if_not_exists()
+          .col(pk_uuid(AccessKey::Id))
+          .col(string_uniq(AccessKey::KeyId))
+          .col(string(AccessKey::Secret))
+          .col(uuid(AccessKey::User))
+          .col(boolean(AccessKey::Enabled))
+          .col(date_time(AccessKey::Created))
+          .col(date_time_null(AccessKey::LastUsed))
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(AccessKey::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(DeriveIden)]
+enum AccessKey {
+  Table,
+  Id,
+  KeyId,
+  Secret,
+  User,
+  Enabled,
+  Created,
+  LastUsed,
+}
@@ -25,9 +25,29 @@ pub struct EnvConfig {
   // storage
   pub storage_type: StorageType,
   pub storage_path: PathBuf,
+  /// directories holding the data of redundant storage types
+  pub storage_paths: Vec<PathBuf>,
+  /// parity shards of erasure coded storage, at least two, the other `storage_paths` hold data shards
+  pub storage_parity_shards: usize,
+  /// seconds between scrubs of redundant storage, 0 disables scrubbing
+  pub scrub_interval: u64,
 
   // s3
   pub s3_port: u16,
+  /// region reported for buckets and expected in signatures by default
+  pub s3_region: String,
+  /// additional regions requests may be signed for and buckets may be created in
+  pub s3_allowed_regions: Vec<String>,
+  /// domain for virtual-hosted-style addressing like `bucket.s3.example.com`
+  pub s3_domain: Option<String>,
+  /// public url of the s3 api, presigned urls point to it
+  pub s3_url: Url,
+  /// seconds after which unfinished multipart uploads are aborted
+  pub multipart_max_age: u64,
+  /// largest file accepted by browser form uploads in bytes
+  pub form_upload_max_size: u64,
+  /// accept requests without a signature, they bypass the access keys entirely
+  pub s3_allow_anonymous: bool,
 
   pub metrics_enabled: bool,
   pub metrics_name: String,
@@ -54,7 +74,17 @@ impl Default for EnvConfig {
       base_url: Url::parse("http://localhost:8080").unwrap(),
       storage_type: StorageType::NoRaid,
       storage_path: PathBuf::from("/data"),
+      storage_paths: vec![],
+      storage_parity_shards: 2,
+      scrub_interval: 7 * 24 * 60 * 60,
       s3_port: 9000,
+      s3_region: "us-east-1".to_string(),
+      s3_allowed_regions: vec![],
+      s3_domain: None,
+      s3_url: Url::parse("http://localhost:9000").unwrap(),
+      multipart_max_age: 7 * 24 * 60 * 60,
+      form_upload_max_size: 5 * 1024 * 1024 * 1024,
+      s3_allow_anonymous: false,
       metrics_enabled: true,
       metrics_name: "sagittarius".to_string(),
       metrics_labels: vec![],
@@ -136,6 +166,36 @@ mod test {
     assert_eq!(cfg.storage_path, PathBuf::from("/tmp/s3_"));
   }
 
+  #[test]
+  fn test_storage_paths() {
+    unsafe {
+      std::env::set_var("STORAGE_PATHS", "[/mnt/a, /mnt/b]");
+    }
+    let cfg = config();
+    assert_eq!(
+      cfg.storage_paths,
+      vec![PathBuf::from("/mnt/a"), PathBuf::from("/mnt/b")]
+    );
+  }
+
+  #[test]
+  fn test_storage_parity_shards() {
+    unsafe {
+      std::env::set_var("STORAGE_PARITY_SHARDS", "3");
+    }
+    let cfg = config();
+    assert_eq!(cfg.storage_parity_shards, 3);
+  }
+
+  #[test]
+  fn test_scrub_interval() {
+    unsafe {
+      std::env::set_var("SCRUB_INTERVAL", "0");
+    }
+    let cfg = config();
+    assert_eq!(cfg.scrub_interval, 0);
+  }
+
   #[test]
   fn test_s3_port() {
     unsafe {
@@ -145,6 +205,62 @@ mod test {
     assert_eq!(cfg.s3_port, 9010);
   }
 
+  #[test]
+  fn test_s3_region() {
+    unsafe {
+      std::env::set_var("S3_REGION", "eu-central-1");
+      std::env::set_var("S3_ALLOWED_REGIONS", "[us-east-1, eu-west-1]");
+    }
+    let cfg = config();
+    assert_eq!(cfg.s3_region, "eu-central-1");
+    assert_eq!(cfg.s3_allowed_regions, vec!["us-east-1", "eu-west-1"]);
+  }
+
+  #[test]
+  fn test_s3_domain() {
+    unsafe {
+      std::env::set_var("S3_DOMAIN", "s3.example.com");
+    }
+    let cfg = config();
+    assert_eq!(cfg.s3_domain.as_deref(), Some("s3.example.com"));
+  }
+
+  #[test]
+  fn test_s3_url() {
+    unsafe {
+      std::env::set_var("S3_URL", "https://s3.example.com");
+    }
+    let cfg = config();
+    assert_eq!(cfg.s3_url.as_str(), "https://s3.example.com/");
+  }
+
+  #[test]
+  fn test_multipart_max_age() {
+    unsafe {
+      std::env::set_var("MULTIPART_MAX_AGE", "3600");
+    }
+    let cfg = config();
+    assert_eq!(cfg.multipart_max_age, 3600);
+  }
+
+  #[test]
+  fn test_form_upload_max_size() {
+    unsafe {
+      std::env::set_var("FORM_UPLOAD_MAX_SIZE", "1024");
+    }
+    let cfg = config();
+    assert_eq!(cfg.form_upload_max_size, 1024);
+  }
+
+  #[test]
+  fn test_s3_allow_anonymous() {
+    unsafe {
+      std::env::set_var("S3_ALLOW_ANONYMOUS", "true");
+    }
+    let cfg = config();
+    assert!(cfg.s3_allow_anonymous);
+  }
+
   #[test]
   fn test_database_max_connections() {
     unsafe {
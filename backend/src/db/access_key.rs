@@ -0,0 +1,165 @@
+use centaurus::{bail, error::Result};
+use chrono::NaiveDateTime;
+use entity::{access_key, prelude::*};
+use sea_orm::{ActiveValue::Set, QueryOrder, prelude::*};
+use tracing::instrument;
+
+pub struct AccessKeyTable<'db> {
+  db: &'db DatabaseConnection,
+}
+
+impl<'db> AccessKeyTable<'db> {
+  pub fn new(db: &'db DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  #[instrument(skip(self))]
+  pub async fn get_access_key(&self, access_key_id: &str) -> Result<Option<access_key::Model>> {
+    let res = AccessKey::find()
+      .filter(access_key::Column::KeyId.eq(access_key_id))
+      .one(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  #[instrument(skip(self))]
+  pub async fn list_access_keys(&self, user: Uuid) -> Result<Vec<access_key::Model>> {
+    let res = AccessKey::find()
+      .filter(access_key::Column::User.eq(user))
+      .order_by_asc(access_key::Column::Created)
+      .all(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  #[instrument(skip(self, key))]
+  pub async fn create_access_key(&self, key: access_key::Model) -> Result<()> {
+    let key: access_key::ActiveModel = key.into();
+    key.insert(self.db).await?;
+
+    Ok(())
+  }
+
+  #[instrument(skip(self))]
+  pub async fn update_last_used(&self, id: Uuid, last_used: NaiveDateTime) -> Result<()> {
+    let key = access_key::ActiveModel {
+      id: Set(id),
+      last_used: Set(Some(last_used)),
+      ..Default::default()
+    };
+    key.update(self.db).await?;
+
+    Ok(())
+  }
+
+  /// Updates the state of a key owned by the user
+  #[instrument(skip(self))]
+  pub async fn update_access_key(
+    &self,
+    key_id: &str,
+    user: Uuid,
+    enabled: bool,
+    expires: Option<NaiveDateTime>,
+  ) -> Result<access_key::Model> {
+    let Some(key) = self.get_access_key(key_id).await? else {
+      bail!(NOT_FOUND, "Access key {key_id} not found");
+    };
+    if key.user != user {
+      bail!(NOT_FOUND, "Access key {key_id} not found");
+    }
+
+    let key = access_key::ActiveModel {
+      id: Set(key.id),
+      enabled: Set(enabled),
+      expires: Set(expires),
+      ..Default::default()
+    };
+    let res = key.update(self.db).await?;
+
+    Ok(res)
+  }
+
+  #[instrument(skip(self))]
+  pub async fn delete_access_key(&self, key_id: &str, user: Uuid) -> Result<()> {
+    let result = AccessKey::delete_many()
+      .filter(access_key::Column::KeyId.eq(key_id))
+      .filter(access_key::Column::User.eq(user))
+      .exec(self.db)
+      .await?;
+    if result.rows_affected == 0 {
+      bail!(NOT_FOUND, "Access key {key_id} not found");
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::*;
+  use crate::db::test::test_db;
+
+  #[tokio::test]
+  async fn test_access_key_table() {
+    let db = test_db().await;
+    let table = db.access_key();
+
+    let key = access_key::Model {
+      id: Uuid::new_v4(),
+      key_id: "AKTEST".into(),
+      secret: "encrypted".into(),
+      user: Uuid::new_v4(),
+      enabled: true,
+      created: Utc::now().naive_utc(),
+      last_used: None,
+      expires: None,
+    };
+    table.create_access_key(key.clone()).await.unwrap();
+    assert!(table.create_access_key(key.clone()).await.is_err());
+
+    let saved = table.get_access_key("AKTEST").await.unwrap().unwrap();
+    assert_eq!(saved, key);
+    assert!(table.get_access_key("AKOTHER").await.unwrap().is_none());
+
+    let now = Utc::now().naive_utc();
+    table.update_last_used(key.id, now).await.unwrap();
+    let saved = table.get_access_key("AKTEST").await.unwrap().unwrap();
+    assert_eq!(saved.last_used, Some(now));
+
+    assert_eq!(table.list_access_keys(key.user).await.unwrap().len(), 1);
+    assert!(
+      table
+        .list_access_keys(Uuid::new_v4())
+        .await
+        .unwrap()
+        .is_empty()
+    );
+
+    // keys of other users can't be changed
+    assert!(
+      table
+        .update_access_key("AKTEST", Uuid::new_v4(), false, None)
+        .await
+        .is_err()
+    );
+    let updated = table
+      .update_access_key("AKTEST", key.user, false, Some(now))
+      .await
+      .unwrap();
+    assert!(!updated.enabled);
+    assert_eq!(updated.expires, Some(now));
+
+    assert!(
+      table
+        .delete_access_key("AKTEST", Uuid::new_v4())
+        .await
+        .is_err()
+    );
+    table.delete_access_key("AKTEST", key.user).await.unwrap();
+    assert!(table.get_access_key("AKTEST").await.unwrap().is_none());
+  }
+}
@@ -0,0 +1,106 @@
+use centaurus::{bail, error::Result};
+use entity::{bucket, prelude::*};
+use sea_orm::{QueryOrder, SqlErr, prelude::*};
+use tracing::instrument;
+
+pub struct BucketTable<'db> {
+  db: &'db DatabaseConnection,
+}
+
+impl<'db> BucketTable<'db> {
+  pub fn new(db: &'db DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  #[instrument(skip(self))]
+  pub async fn get_bucket(&self, name: &str) -> Result<Option<bucket::Model>> {
+    let res = Bucket::find()
+      .filter(bucket::Column::Name.eq(name))
+      .one(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  /// Fails with `CONFLICT` if a bucket with the name exists, even if it was created concurrently
+  #[instrument(skip(self))]
+  pub async fn create_bucket(&self, bucket: bucket::Model) -> Result<()> {
+    let name = bucket.name.clone();
+    let bucket: bucket::ActiveModel = bucket.into();
+    match bucket.insert(self.db).await {
+      Ok(_) => Ok(()),
+      Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+        bail!(CONFLICT, "Bucket {name} already exists")
+      }
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  /// Lists all buckets sorted by name
+  #[instrument(skip(self))]
+  pub async fn list_buckets(&self) -> Result<Vec<bucket::Model>> {
+    let res = Bucket::find()
+      .order_by_asc(bucket::Column::Name)
+      .all(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  #[instrument(skip(self))]
+  pub async fn delete_bucket(&self, name: &str) -> Result<()> {
+    let result = Bucket::delete_many()
+      .filter(bucket::Column::Name.eq(name))
+      .exec(self.db)
+      .await?;
+    if result.rows_affected == 0 {
+      bail!(NOT_FOUND, "Bucket {name} not found");
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::*;
+  use crate::db::test::test_db;
+
+  fn bucket(name: &str) -> bucket::Model {
+    bucket::Model {
+      id: Uuid::new_v4(),
+      name: name.into(),
+      owner: None,
+      region: "us-east-1".into(),
+      settings: serde_json::json!({}),
+      created: Utc::now().naive_utc(),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_bucket_table() {
+    let db = test_db().await;
+    let table = db.bucket();
+
+    table.create_bucket(bucket("zeta")).await.unwrap();
+    table.create_bucket(bucket("alpha")).await.unwrap();
+    let err = table.create_bucket(bucket("alpha")).await.unwrap_err();
+    assert_eq!(err.status, http::StatusCode::CONFLICT);
+
+    let names: Vec<_> = table
+      .list_buckets()
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|bucket| bucket.name)
+      .collect();
+    assert_eq!(names, vec!["alpha", "zeta"]);
+    assert!(table.get_bucket("zeta").await.unwrap().is_some());
+
+    table.delete_bucket("zeta").await.unwrap();
+    assert!(table.get_bucket("zeta").await.unwrap().is_none());
+    assert!(table.delete_bucket("zeta").await.is_err());
+  }
+}
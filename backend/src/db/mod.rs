@@ -5,9 +5,12 @@ use migration::MigratorTrait;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 use tracing::instrument;
 
+mod access_key;
+mod bucket;
 mod config;
 mod invalid_jwt;
 mod key;
+mod object;
 mod user;
 
 #[instrument(skip(config))]
@@ -32,7 +35,7 @@ pub async fn init_db(config: &crate::config::EnvConfig) -> Connection {
   Connection(conn)
 }
 
-#[derive(FromReqExtension, Clone)]
+#[derive(FromReqExtension, Clone, Debug)]
 pub struct Connection(DatabaseConnection);
 
 impl Deref for Connection {
@@ -44,10 +47,22 @@ impl Deref for Connection {
 }
 
 impl Connection {
+  pub fn access_key(&self) -> access_key::AccessKeyTable<'_> {
+    access_key::AccessKeyTable::new(&self.0)
+  }
+
+  pub fn bucket(&self) -> bucket::BucketTable<'_> {
+    bucket::BucketTable::new(&self.0)
+  }
+
   pub fn key(&self) -> key::KeyTable<'_> {
     key::KeyTable::new(&self.0)
   }
 
+  pub fn object(&self) -> object::ObjectTable<'_> {
+    object::ObjectTable::new(&self.0)
+  }
+
   pub fn user(&self) -> user::UserTable<'_> {
     user::UserTable::new(&self.0)
   }
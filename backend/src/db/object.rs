@@ -0,0 +1,161 @@
+use centaurus::error::Result;
+use entity::{object, prelude::*};
+use sea_orm::{prelude::*, sea_query::OnConflict};
+use tracing::instrument;
+
+pub struct ObjectTable<'db> {
+  db: &'db DatabaseConnection,
+}
+
+impl<'db> ObjectTable<'db> {
+  pub fn new(db: &'db DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  #[instrument(skip(self))]
+  pub async fn get_object(&self, bucket: &str, key: &str) -> Result<Option<object::Model>> {
+    let res = Object::find()
+      .filter(object::Column::Bucket.eq(bucket))
+      .filter(object::Column::Key.eq(key))
+      .one(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  /// Loads the objects of the given keys that have a record
+  #[instrument(skip(self))]
+  pub async fn get_objects(&self, bucket: &str, keys: &[String]) -> Result<Vec<object::Model>> {
+    let res = Object::find()
+      .filter(object::Column::Bucket.eq(bucket))
+      .filter(object::Column::Key.is_in(keys))
+      .all(self.db)
+      .await?;
+
+    Ok(res)
+  }
+
+  /// Inserts the object or replaces the existing one with the same bucket and key
+  #[instrument(skip(self))]
+  pub async fn save_object(&self, object: object::Model) -> Result<()> {
+    let object: object::ActiveModel = object.into();
+
+    Object::insert(object)
+      .on_conflict(
+        OnConflict::columns([object::Column::Bucket, object::Column::Key])
+          .update_columns([
+            object::Column::Size,
+            object::Column::Etag,
+            object::Column::ContentType,
+            object::Column::CacheControl,
+            object::Column::ContentDisposition,
+            object::Column::Metadata,
+            object::Column::Created,
+            object::Column::Parts,
+            object::Column::Checksum,
+          ])
+          .to_owned(),
+      )
+      .exec(self.db)
+      .await?;
+
+    Ok(())
+  }
+
+  /// Inserts the object unless the key already has one, which is left untouched
+  #[instrument(skip(self))]
+  pub async fn insert_object(&self, object: object::Model) -> Result<()> {
+    let object: object::ActiveModel = object.into();
+
+    Object::insert(object)
+      .on_conflict(
+        OnConflict::columns([object::Column::Bucket, object::Column::Key])
+          .do_nothing()
+          .to_owned(),
+      )
+      .do_nothing()
+      .exec(self.db)
+      .await?;
+
+    Ok(())
+  }
+
+  #[instrument(skip(self))]
+  pub async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
+    Object::delete_many()
+      .filter(object::Column::Bucket.eq(bucket))
+      .filter(object::Column::Key.eq(key))
+      .exec(self.db)
+      .await?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::*;
+  use crate::db::test::test_db;
+
+  fn object(etag: &str) -> object::Model {
+    object::Model {
+      id: Uuid::new_v4(),
+      bucket: "bucket".into(),
+      key: "dir/key".into(),
+      size: 4,
+      etag: etag.into(),
+      content_type: Some("text/plain".into()),
+      cache_control: None,
+      content_disposition: None,
+      metadata: serde_json::json!({ "color": "blue" }),
+      created: Utc::now().naive_utc(),
+      parts: None,
+      checksum: None,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_object_table() {
+    let db = test_db().await;
+    let table = db.object();
+
+    table.save_object(object("first")).await.unwrap();
+    // saving the same key again replaces the object
+    table.save_object(object("second")).await.unwrap();
+
+    let saved = table
+      .get_object("bucket", "dir/key")
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(saved.etag, "second");
+    assert_eq!(saved.content_type.as_deref(), Some("text/plain"));
+
+    // inserting does not replace an existing object
+    table.insert_object(object("third")).await.unwrap();
+    let saved = table
+      .get_object("bucket", "dir/key")
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(saved.etag, "second");
+    assert!(table.get_object("bucket", "other").await.unwrap().is_none());
+    let objects = table
+      .get_objects("bucket", &["dir/key".into(), "other".into()])
+      .await
+      .unwrap();
+    assert_eq!(objects.len(), 1);
+    assert_eq!(objects[0].etag, "second");
+
+    table.delete_object("bucket", "dir/key").await.unwrap();
+    assert!(
+      table
+        .get_object("bucket", "dir/key")
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+}
@@ -16,7 +16,6 @@ impl<'db> UserTable<'db> {
     Self { db }
   }
 
-  #[allow(unused)]
   #[instrument(skip(self))]
   pub async fn get_user(&self, id: Uuid) -> Result<entity::user::Model> {
     let res = entity::user::Entity::find_by_id(id).one(self.db).await?;
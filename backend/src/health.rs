@@ -1,9 +1,16 @@
 use axum::{Router, routing::get};
 
+use crate::s3::interface::S3Interface;
+
 pub fn router() -> Router {
   Router::new().route("/health", get(health_check))
 }
 
-async fn health_check() -> &'static str {
-  "OK"
+/// Redundant storage keeps serving requests while degraded, so this is not an error
+async fn health_check(interface: S3Interface) -> &'static str {
+  if interface.degraded().is_empty() {
+    "OK"
+  } else {
+    "DEGRADED"
+  }
 }
@@ -0,0 +1,147 @@
+use axum::{
+  Json, Router,
+  extract::Path,
+  response::IntoResponse,
+  routing::{get, put},
+};
+use centaurus::error::Result;
+use chrono::{DateTime, Utc};
+use http::{StatusCode, header::CACHE_CONTROL};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use crate::{auth::jwt_auth::JwtAuth, db::Connection, s3::auth::access_key::AccessKeys};
+
+pub fn router() -> Router {
+  Router::new()
+    .route("/", get(list_keys).post(create_key))
+    .route("/{key_id}", put(update_key).delete(delete_key))
+}
+
+/// Owner of the keys of a logged in user, OIDC subjects are mapped to a stable uuid
+pub fn key_user(user_id: &str) -> Uuid {
+  Uuid::parse_str(user_id)
+    .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_OID, user_id.as_bytes()))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct KeyInfo {
+  key_id: String,
+  enabled: bool,
+  created: DateTime<Utc>,
+  last_used: Option<DateTime<Utc>>,
+  expires: Option<DateTime<Utc>>,
+}
+
+impl From<entity::access_key::Model> for KeyInfo {
+  fn from(key: entity::access_key::Model) -> Self {
+    Self {
+      key_id: key.key_id,
+      enabled: key.enabled,
+      created: key.created.and_utc(),
+      last_used: key.last_used.map(|last_used| last_used.and_utc()),
+      expires: key.expires.map(|expires| expires.and_utc()),
+    }
+  }
+}
+
+#[instrument(skip(db))]
+async fn list_keys(auth: JwtAuth, db: Connection) -> Result<Json<Vec<KeyInfo>>> {
+  let keys = db
+    .access_key()
+    .list_access_keys(key_user(&auth.user_id))
+    .await?;
+
+  Ok(Json(keys.into_iter().map(KeyInfo::from).collect()))
+}
+
+#[derive(Deserialize, Debug)]
+struct CreateKeyReq {
+  expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreateKeyRes {
+  #[serde(flatten)]
+  key: KeyInfo,
+  /// only returned once, it can't be retrieved later
+  secret: String,
+}
+
+#[instrument(skip(keys))]
+async fn create_key(
+  auth: JwtAuth,
+  keys: AccessKeys,
+  Json(payload): Json<CreateKeyReq>,
+) -> Result<impl IntoResponse> {
+  let (key, secret) = keys
+    .create(
+      key_user(&auth.user_id),
+      payload.expires.map(|expires| expires.naive_utc()),
+    )
+    .await?;
+  debug!("Access key {} created for {}", key.key_id, auth.user_id);
+
+  Ok((
+    [(CACHE_CONTROL, "no-store")],
+    Json(CreateKeyRes {
+      key: key.into(),
+      secret,
+    }),
+  ))
+}
+
+#[derive(Deserialize, Debug)]
+struct UpdateKeyReq {
+  enabled: bool,
+  expires: Option<DateTime<Utc>>,
+}
+
+#[instrument(skip(db))]
+async fn update_key(
+  auth: JwtAuth,
+  db: Connection,
+  Path(key_id): Path<String>,
+  Json(payload): Json<UpdateKeyReq>,
+) -> Result<Json<KeyInfo>> {
+  let key = db
+    .access_key()
+    .update_access_key(
+      &key_id,
+      key_user(&auth.user_id),
+      payload.enabled,
+      payload.expires.map(|expires| expires.naive_utc()),
+    )
+    .await?;
+
+  Ok(Json(key.into()))
+}
+
+#[instrument(skip(db))]
+async fn delete_key(
+  auth: JwtAuth,
+  db: Connection,
+  Path(key_id): Path<String>,
+) -> Result<StatusCode> {
+  db.access_key()
+    .delete_access_key(&key_id, key_user(&auth.user_id))
+    .await?;
+  debug!("Access key {key_id} deleted by {}", auth.user_id);
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_key_user() {
+    let id = Uuid::new_v4();
+    assert_eq!(key_user(&id.to_string()), id);
+    // OIDC subjects always map to the same user
+    assert_eq!(key_user("subject"), key_user("subject"));
+    assert_ne!(key_user("subject"), key_user("other"));
+  }
+}
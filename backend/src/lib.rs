@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use axum::{Extension, Router};
 use centaurus::init::{
   axum::{add_base_layers, listener_setup, run_app},
@@ -9,7 +11,9 @@ use tracing::{info, instrument};
 
 use crate::{
   config::{AppConfig, EnvConfig},
+  db::Connection,
   macros::DualRouterExt,
+  s3::interface::{S3Interface, ScrubMetrics},
 };
 
 mod auth;
@@ -17,7 +21,9 @@ mod config;
 mod db;
 mod frontend;
 mod health;
+mod keys;
 mod macros;
+mod presign;
 mod s3;
 
 #[derive(Debug)]
@@ -38,6 +44,21 @@ impl App {
     fs::create_dir_all(&config.storage_path)
       .await
       .expect("failed to create storage path");
+    let db = db::init_db(&config).await;
+    let interface = config
+      .storage_type
+      .storage(&config, db.clone())
+      .await
+      .expect("Failed to initialize storage");
+    interface
+      .clone()
+      .spawn_upload_gc(Duration::from_secs(config.multipart_max_age));
+    if interface.is_redundant() && config.scrub_interval > 0 {
+      let metrics = ScrubMetrics::new(config.metrics_name.clone(), config.metrics_labels.clone());
+      interface
+        .clone()
+        .spawn_scrubber(Duration::from_secs(config.scrub_interval), metrics);
+    }
 
     let metrics_enabled = config.metrics_enabled;
     let metrics_name = config.metrics_name.clone();
@@ -47,7 +68,7 @@ impl App {
     let s3_listener = listener_setup(config.s3_port).await;
 
     let (mut app, mut s3) = (router(&config).await, s3_router(&config).await)
-      .state(config)
+      .state(config, db, interface)
       .await;
 
     use centaurus::init::metrics::metrics;
@@ -96,6 +117,8 @@ async fn router(config: &EnvConfig) -> Router {
       "/api",
       Router::new()
         .nest("/auth", auth::router())
+        .nest("/keys", keys::router())
+        .nest("/presign", presign::router())
         .merge(health::router())
         .metrics_route()
         .await,
@@ -106,24 +129,24 @@ async fn router(config: &EnvConfig) -> Router {
 
 #[instrument(skip(config))]
 async fn s3_router(config: &EnvConfig) -> Router {
-  s3::router().add_base_layers(&config.base).await
+  s3::router(config).add_base_layers(&config.base).await
 }
 
 router_extension!(
-  async fn state(self, env_config: EnvConfig) -> Self {
+  async fn state(self, env_config: EnvConfig, db: Connection, interface: S3Interface) -> Self {
     use auth::auth;
     use config::config;
     use frontend::frontend;
     use s3::s3;
 
-    let db = db::init_db(&env_config).await;
     let app_config = AppConfig::new(&db).await;
 
+    // auth runs first so the initial user exists when s3 is set up
     self
-      .s3(&env_config)
-      .await
       .auth(&env_config, &app_config, &db)
       .await
+      .s3(interface, &env_config, &db)
+      .await
       .frontend()
       .await
       .config(&db)
@@ -143,5 +166,6 @@ mod test {
     }
     // test if there are any handler setup error that are not caught at compile time
     let _ = super::router(&super::EnvConfig::parse()).await;
+    let _ = super::s3_router(&super::EnvConfig::parse()).await;
   }
 }
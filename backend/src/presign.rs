@@ -0,0 +1,101 @@
+use axum::{Json, Router, response::IntoResponse, routing::post};
+use centaurus::{bail, error::Result};
+use chrono::{DateTime, Duration, Utc};
+use http::{Method, header::CACHE_CONTROL};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use url::Url;
+
+use crate::{
+  auth::jwt_auth::JwtAuth,
+  config::EnvConfig,
+  db::Connection,
+  keys::key_user,
+  s3::auth::{access_key::AccessKeys, query::presign_url},
+};
+
+pub fn router() -> Router {
+  Router::new().route("/", post(presign))
+}
+
+#[derive(Deserialize, Debug)]
+struct PresignReq {
+  bucket: String,
+  key: String,
+  method: String,
+  /// seconds the url is valid for
+  expires: i64,
+  /// access key to sign with, defaults to the oldest active key of the user
+  key_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PresignRes {
+  url: Url,
+  key_id: String,
+  expires: DateTime<Utc>,
+}
+
+#[instrument(skip(db, keys, config))]
+async fn presign(
+  auth: JwtAuth,
+  db: Connection,
+  keys: AccessKeys,
+  config: EnvConfig,
+  Json(payload): Json<PresignReq>,
+) -> Result<impl IntoResponse> {
+  let method = match payload.method.to_ascii_uppercase().as_str() {
+    "GET" => Method::GET,
+    "HEAD" => Method::HEAD,
+    "PUT" => Method::PUT,
+    "DELETE" => Method::DELETE,
+    _ => bail!("Method {} can not be presigned", payload.method),
+  };
+  if payload.bucket.is_empty() || payload.key.is_empty() {
+    bail!("Bucket and key are required");
+  }
+
+  let now = Utc::now();
+  let Some(key) = db
+    .access_key()
+    .list_access_keys(key_user(&auth.user_id))
+    .await?
+    .into_iter()
+    .find(|key| match &payload.key_id {
+      Some(key_id) => &key.key_id == key_id,
+      None => key.enabled && key.expires.is_none_or(|expires| expires > now.naive_utc()),
+    })
+  else {
+    bail!(NOT_FOUND, "No access key to sign the url with");
+  };
+  let secret_key = keys.lookup(&key.key_id).await?;
+
+  let mut url = config.s3_url.clone();
+  {
+    let Ok(mut segments) = url.path_segments_mut() else {
+      bail!(INTERNAL_SERVER_ERROR, "S3 url can not have a path");
+    };
+    segments
+      .pop_if_empty()
+      .push(&payload.bucket)
+      .extend(payload.key.split('/'));
+  }
+  let url = presign_url(
+    &secret_key,
+    &config.s3_region,
+    method,
+    url,
+    payload.expires,
+    now,
+  )?;
+  debug!("Url presigned for {} with {}", auth.user_id, key.key_id);
+
+  Ok((
+    [(CACHE_CONTROL, "no-store")],
+    Json(PresignRes {
+      url,
+      key_id: key.key_id,
+      expires: now + Duration::seconds(payload.expires),
+    }),
+  ))
+}
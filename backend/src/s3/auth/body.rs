@@ -9,11 +9,28 @@ use tokio::{
 use tracing::instrument;
 use uuid::Uuid;
 
+use crate::s3::{
+  auth::stream::BodyStream,
+  checksum::Checksum,
+  error::{S3Error, S3ErrorCode},
+};
+
+/// Xml bodies are buffered in memory, so they are limited unlike object bodies
+const MAX_XML_SIZE: usize = 1024 * 1024;
+
 #[async_trait::async_trait]
-pub trait Body: Sized {
+pub trait Body: Sized + Send {
   type Writer: BodyWriter + Send;
 
   async fn from_writer(writer: Self::Writer) -> Result<Self>;
+
+  /// Receives the verified request body before the handler runs, returns the body
+  /// with the checksum it was verified against
+  async fn from_stream(stream: BodyStream) -> Result<(Self, Option<Checksum>)> {
+    let mut writer = Self::Writer::new().await?;
+    let checksum = stream.write_to(&mut writer).await?;
+    Ok((Self::from_writer(writer).await?, checksum))
+  }
 }
 
 /// A temporary file that will be deleted when dropped
@@ -78,13 +95,18 @@ impl<T> Body for Xml<T>
 where
   T: for<'de> Deserialize<'de> + Send + Sync + 'static,
 {
-  type Writer = Vec<u8>;
+  type Writer = XmlWriter;
 
   async fn from_writer(writer: Self::Writer) -> Result<Self> {
-    Ok(Xml::from_slice(&writer)?)
+    Xml::from_slice(&writer.0)
+      .map_err(|e| S3Error::new(S3ErrorCode::MalformedXml, e.to_string()).into())
   }
 }
 
+/// Buffers an xml document, rejecting documents larger than [`MAX_XML_SIZE`]
+#[derive(Debug)]
+pub struct XmlWriter(Vec<u8>);
+
 #[async_trait::async_trait]
 pub trait BodyWriter: Sized + Debug {
   async fn new() -> Result<Self>;
@@ -134,6 +156,27 @@ impl BodyWriter for Vec<u8> {
   }
 }
 
+#[async_trait::async_trait]
+impl BodyWriter for XmlWriter {
+  async fn new() -> Result<Self> {
+    Ok(XmlWriter(Vec::new()))
+  }
+
+  async fn write(&mut self, buf: &[u8]) -> Result<()> {
+    if self.0.len() + buf.len() > MAX_XML_SIZE {
+      return Err(
+        S3Error::new(
+          S3ErrorCode::EntityTooLarge,
+          format!("XML body must be at most {MAX_XML_SIZE} bytes"),
+        )
+        .into(),
+      );
+    }
+    self.0.extend_from_slice(buf);
+    Ok(())
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
@@ -168,4 +211,11 @@ mod test {
     let body = <Vec<u8> as Body>::from_writer(writer).await.unwrap();
     assert_eq!(body, b"Hello, world!");
   }
+
+  #[tokio::test]
+  async fn test_xml_size_limit() {
+    let mut writer = XmlWriter::new().await.unwrap();
+    writer.write(&vec![b' '; MAX_XML_SIZE]).await.unwrap();
+    assert!(writer.write(b" ").await.is_err());
+  }
 }
@@ -1,12 +1,20 @@
-use std::str::FromStr;
+use std::{
+  fmt::{self, Display},
+  str::FromStr,
+};
 
 use axum_extra::headers::authorization::Credentials;
-use centaurus::{bail, error::ErrorReport};
+use centaurus::error::ErrorReport;
 use chrono::NaiveDate;
 use eyre::Context;
 use http::HeaderValue;
 use tracing::instrument;
 
+use crate::s3_bail;
+
+/// Date of the credential scope
+pub const CREDENTIAL_DATE_FORMAT: &str = "%Y%m%d";
+
 #[derive(Debug)]
 pub struct AWS4 {
   pub credential: AWS4Credential,
@@ -86,6 +94,16 @@ impl Credentials for AWS4 {
   }
 }
 
+impl Display for AWS4Credential {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}/{}/{}/s3/aws4_request",
+      self.access_key, self.date, self.region
+    )
+  }
+}
+
 impl FromStr for AWS4Credential {
   type Err = ErrorReport;
 
@@ -93,14 +111,17 @@ impl FromStr for AWS4Credential {
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     let parts = s.split('/').collect::<Vec<_>>();
     if parts.len() != 5 || parts[3] != "s3" || parts[4] != "aws4_request" {
-      bail!("Invalid AWS4 credential format {s}");
+      s3_bail!(
+        AuthorizationHeaderMalformed,
+        "Invalid AWS4 credential format {s}"
+      );
     }
 
-    let date =
-      NaiveDate::parse_from_str(parts[1], "%Y%m%d").context("Invalid date in AWS4 credential")?;
+    let date = NaiveDate::parse_from_str(parts[1], CREDENTIAL_DATE_FORMAT)
+      .context("Invalid date in AWS4 credential")?;
     // check if date is older than 7 days
     if (chrono::Utc::now().naive_utc().date() - date).num_days() > 7 {
-      bail!(FORBIDDEN, "AWS4 credential is expired");
+      s3_bail!(AccessDenied, "AWS4 credential is expired");
     }
 
     Ok(AWS4Credential {
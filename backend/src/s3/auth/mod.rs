@@ -8,61 +8,93 @@ use axum_extra::{
   TypedHeader,
   headers::{ContentType, Mime},
 };
-use centaurus::error::ErrorReport;
+use eyre::OptionExt;
 use http::Method;
 use tracing::instrument;
 
-use crate::s3::auth::{
-  body::Body, header::header_auth, multipart::multipart_auth, query::query_auth,
+use crate::{
+  config::EnvConfig,
+  s3::{
+    auth::{
+      access_key::{AccessKey, AccessKeys},
+      body::Body,
+      header::header_auth,
+      multipart::multipart_auth,
+      query::query_auth,
+    },
+    checksum::Checksum,
+    error::S3ErrorResponse,
+    region::Regions,
+  },
 };
 
-mod body;
-mod credential;
+pub mod access_key;
+pub mod body;
+pub mod credential;
 mod header;
 mod multipart;
-mod query;
+mod policy;
+pub mod query;
 mod sig_v4;
+pub mod stream;
 
-/// TODO: DB: credential lookup, region check
 /// https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-header-based-auth.html
 pub struct S3Auth<T: Body = ()> {
   pub identity: Identity,
-  #[allow(unused)]
   pub body: T,
-  #[allow(unused)]
-  /// TODO: currently only used for multipart uploads which are not implemented yet
+  /// fields of a browser form upload with lowercase names, `None` for other requests
   pub additional: Option<HashMap<String, String>>,
+  /// checksum of the body that was verified against the request
+  pub checksum: Option<Checksum>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Identity {
-  AccessKey(String),
+  AccessKey(AccessKey),
   Anonymous,
 }
 
-const SECRET: &str = "secret";
-
 impl<S: Sync + Send, T: Body> FromRequest<S> for S3Auth<T> {
-  type Rejection = ErrorReport;
+  type Rejection = S3ErrorResponse;
 
   #[instrument(skip(_state))]
   async fn from_request(req: Request, _state: &S) -> std::result::Result<Self, Self::Rejection> {
     let (mut req, body) = req.into_parts();
+    let keys = req
+      .extensions
+      .get::<AccessKeys>()
+      .cloned()
+      .ok_or_eyre("Access keys are not initialized")?;
+    let regions = req
+      .extensions
+      .get::<Regions>()
+      .cloned()
+      .ok_or_eyre("Regions are not initialized")?;
+    let config = req
+      .extensions
+      .get::<EnvConfig>()
+      .cloned()
+      .ok_or_eyre("Config is not initialized")?;
+    let allow_anonymous = config.s3_allow_anonymous;
+
     if req.method == Method::POST
       && let Ok(TypedHeader(mime)) = req.extract::<TypedHeader<ContentType>>().await
     {
       let mime: Mime = mime.into();
       if mime.type_() == mime::MULTIPART && mime.subtype() == mime::FORM_DATA {
-        return multipart_auth(Request::from_parts(req, body)).await;
+        let req = Request::from_parts(req, body);
+        let max_size = config.form_upload_max_size;
+        return Ok(multipart_auth(req, &keys, &regions, max_size, allow_anonymous).await?);
       }
     }
 
     let query: Vec<(String, String)> =
       serde_urlencoded::from_str(req.uri.query().unwrap_or("")).unwrap_or_default();
     if query.iter().any(|(k, _)| k == "X-Amz-Signature") {
-      return query_auth(Request::from_parts(req, body), &query).await;
+      return Ok(query_auth(Request::from_parts(req, body), &query, &keys, &regions).await?);
     }
 
-    header_auth(Request::from_parts(req, body)).await
+    let req = Request::from_parts(req, body);
+    Ok(header_auth(req, &keys, &regions, allow_anonymous).await?)
   }
 }
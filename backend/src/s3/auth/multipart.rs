@@ -2,54 +2,99 @@ use std::collections::HashMap;
 
 use axum::extract::{FromRequest, Multipart, Request};
 use base64::prelude::*;
-use centaurus::{
-  bail,
-  error::{ErrorReportStatusExt, Result},
-};
+use centaurus::error::{ErrorReportStatusExt, Result};
 use chrono::NaiveDateTime;
 use http::StatusCode;
 use tracing::instrument;
 
-use crate::s3::{
-  auth::{
-    Identity, S3Auth, SECRET,
-    body::{Body, BodyWriter},
-    credential::AWS4Credential,
-    sig_v4::{ALGORITHM, StringToSign},
+use crate::{
+  s3::{
+    auth::{
+      Identity, S3Auth,
+      access_key::AccessKeys,
+      body::{Body, BodyWriter},
+      credential::AWS4Credential,
+      policy::PostPolicy,
+      sig_v4::{ALGORITHM, StringToSign, signature_matches},
+    },
+    header::DATE_FORMAT,
+    region::Regions,
   },
-  header::DATE_FORMAT,
+  s3_bail,
 };
 
+/// S3 limits the form fields preceding the file to 20 KB
+const MAX_FIELDS_SIZE: usize = 20 * 1024;
+/// replaced with the name of the uploaded file in the key
+const FILENAME_VARIABLE: &str = "${filename}";
+
 #[instrument]
-pub async fn multipart_auth<T: Body>(req: Request) -> Result<S3Auth<T>> {
+pub async fn multipart_auth<T: Body>(
+  req: Request,
+  keys: &AccessKeys,
+  regions: &Regions,
+  max_size: u64,
+  allow_anonymous: bool,
+) -> Result<S3Auth<T>> {
+  let bucket = req
+    .uri()
+    .path()
+    .trim_start_matches('/')
+    .split('/')
+    .next()
+    .unwrap_or("")
+    .to_string();
   let multipart = Multipart::from_request(req, &()).await?;
   let mut writer = T::Writer::new().await?;
 
-  let data = parse_multipart(multipart, &mut writer).await?;
+  // all signed fields precede the file, so it is only read once the signature and
+  // the policy conditions on the fields match
+  let (signed, data) = parse_multipart(multipart, &mut writer, async |fields| {
+    let Some(auth_info) = auth_info(fields)? else {
+      if !allow_anonymous {
+        s3_bail!(AccessDenied, "Anonymous requests are not allowed");
+      }
+      return Ok((None, max_size));
+    };
+
+    regions.check(&auth_info.credential)?;
+    let policy = PostPolicy::parse(&auth_info.policy)?;
+    let secret_key = keys.lookup(&auth_info.credential.access_key).await?;
+    let signature = StringToSign::new(auth_info.policy).sign(&secret_key, &auth_info.credential)?;
+    if !signature_matches(&signature, &auth_info.signature) {
+      s3_bail!(SignatureDoesNotMatch, "Signature mismatch");
+    }
+    policy.check_fields(&bucket, fields)?;
 
-  let identity = if let Some(auth_info) = data.auth_info {
-    let signature = StringToSign::new(auth_info.policy).sign(SECRET, &auth_info.credential)?;
+    let max_size = policy
+      .max_size()
+      .map_or(max_size, |policy| policy.min(max_size));
+    Ok((Some((secret_key, policy)), max_size))
+  })
+  .await?;
 
-    if signature != auth_info.signature {
-      bail!(FORBIDDEN, "Signature mismatch");
+  let identity = match signed {
+    Some((secret_key, policy)) => {
+      policy.check_size(data.size)?;
+      keys.used(&secret_key).await?;
+      Identity::AccessKey(secret_key.key)
     }
-
-    Identity::AccessKey(auth_info.credential.access_key.clone())
-  } else {
-    Identity::Anonymous
+    None => Identity::Anonymous,
   };
 
   Ok(S3Auth {
     identity,
     body: T::from_writer(writer).await?,
-    additional: Some(data.additional),
+    additional: Some(data.fields),
+    checksum: None,
   })
 }
 
-// TODO: handling of policy
 struct MultipartData {
-  auth_info: Option<MultipartAuthInfo>,
-  additional: HashMap<String, String>,
+  /// all fields except the file with lowercase names
+  fields: HashMap<String, String>,
+  /// size of the file
+  size: u64,
 }
 
 struct MultipartAuthInfo {
@@ -60,49 +105,85 @@ struct MultipartAuthInfo {
   signature: String,
 }
 
-#[instrument]
-async fn parse_multipart(
+/// Streams the file of a form into the writer
+///
+/// `authorize` is called with the fields preceding the file before any of it is read
+/// and returns the largest size the file may have
+#[instrument(skip(authorize))]
+async fn parse_multipart<A>(
   mut multipart: Multipart,
   writer: &mut impl BodyWriter,
-) -> Result<MultipartData> {
-  let mut policy = None;
-  let mut algorithm = None;
-  let mut credential = None;
-  let mut date = None;
-  let mut signature = None;
-  let mut additional = HashMap::new();
+  authorize: impl AsyncFnOnce(&HashMap<String, String>) -> Result<(A, u64)>,
+) -> Result<(A, MultipartData)> {
+  let mut fields: HashMap<String, String> = HashMap::new();
+  let mut fields_size = 0;
 
-  while let Some(field) = multipart.next_field().await? {
-    let name = field.name().unwrap_or("").to_string();
+  while let Some(mut field) = multipart.next_field().await? {
+    let name = field.name().unwrap_or("").to_ascii_lowercase();
 
     if name == "file" {
-      let data = field.bytes().await?;
-      writer.write(&data).await?;
-      continue;
+      let file_name = field.file_name().unwrap_or("");
+      if let Some(key) = fields.get_mut("key") {
+        *key = key.replace(FILENAME_VARIABLE, file_name);
+      }
+      let (authorized, max_size) = authorize(&fields).await?;
+
+      let mut size = 0;
+      while let Some(chunk) = field.chunk().await? {
+        size += chunk.len() as u64;
+        if size > max_size {
+          s3_bail!(
+            EntityTooLarge,
+            "Your proposed upload exceeds the maximum allowed size of {max_size} bytes"
+          );
+        }
+        writer.write(&chunk).await?;
+      }
+
+      drop(field);
+      if multipart.next_field().await?.is_some() {
+        s3_bail!(
+          InvalidArgument,
+          "The file must be the last field of the form"
+        );
+      }
+      return Ok((authorized, MultipartData { fields, size }));
     }
 
-    let value = field.text().await?;
-    match name.as_str() {
-      "policy" => policy = Some(value),
-      "x-amz-algorithm" => algorithm = Some(value),
-      "x-amz-credential" => credential = Some(value),
-      "x-amz-date" => date = Some(value),
-      "x-amz-signature" => signature = Some(value),
-      _ => {
-        additional.insert(name, value.clone());
+    let mut value = Vec::new();
+    while let Some(chunk) = field.chunk().await? {
+      fields_size += chunk.len();
+      if fields_size > MAX_FIELDS_SIZE {
+        s3_bail!(
+          MaxPostPreDataLengthExceededError,
+          "Your POST request fields preceding the upload file were too large"
+        );
       }
+      value.extend_from_slice(&chunk);
     }
+    let value = String::from_utf8(value)
+      .status_context(StatusCode::BAD_REQUEST, "Form field is not valid UTF-8")?;
+    fields.insert(name, value);
   }
 
-  let auth_info = if let Some(algorithm) = algorithm
-    && let Some(credential) = credential
-    && let Some(date) = date
-    && let Some(signature) = signature
-    && let Some(policy) = policy
+  s3_bail!(
+    InvalidArgument,
+    "POST requires exactly one file upload per request"
+  );
+}
+
+/// Signature fields of a form, `None` for anonymous uploads
+fn auth_info(fields: &HashMap<String, String>) -> Result<Option<MultipartAuthInfo>> {
+  let field = |name: &str| fields.get(name).cloned();
+  if let Some(algorithm) = field("x-amz-algorithm")
+    && let Some(credential) = field("x-amz-credential")
+    && let Some(date) = field("x-amz-date")
+    && let Some(signature) = field("x-amz-signature")
+    && let Some(policy) = field("policy")
   {
     // check algorithm
     if algorithm != ALGORITHM {
-      bail!("Only AWS4-HMAC-SHA256 is supported");
+      s3_bail!(InvalidArgument, "Only AWS4-HMAC-SHA256 is supported");
     }
 
     // check policy is valid base64
@@ -110,27 +191,31 @@ async fn parse_multipart(
       .decode(&policy)
       .status_context(StatusCode::BAD_REQUEST, "Invalid policy field")?;
 
-    Some(MultipartAuthInfo {
+    Ok(Some(MultipartAuthInfo {
       _algorithm: algorithm,
       credential: credential.parse()?,
       _date: NaiveDateTime::parse_from_str(&date, DATE_FORMAT).status(StatusCode::BAD_REQUEST)?,
       signature,
       policy,
-    })
+    }))
+  } else if fields.contains_key("policy") {
+    // a policy that is not signed with signature version 4 can not be enforced
+    s3_bail!(
+      InvalidArgument,
+      "Bucket POST with a policy requires the x-amz-algorithm, x-amz-credential, x-amz-date and x-amz-signature fields"
+    );
   } else {
-    None
-  };
-
-  let multipart_data = MultipartData {
-    auth_info,
-    additional,
-  };
-
-  Ok(multipart_data)
+    Ok(None)
+  }
 }
 
 #[cfg(test)]
 mod test {
+  use crate::s3::{
+    auth::access_key::{SecretKey, test::test_keys},
+    region::test::test_regions,
+  };
+  use centaurus::error::ErrorReport;
   use http::header::CONTENT_TYPE;
   use mime::BOUNDARY;
   use std::io::Write;
@@ -148,11 +233,27 @@ mod test {
     write!(multipart, "{}\r\n", content).unwrap();
   }
 
-  fn multipart() -> Result<Request> {
+  fn multipart(uri: &str) -> Result<Request> {
+    multipart_with_max_size(uri, 1024)
+  }
+
+  fn multipart_with_max_size(uri: &str, max_size: u64) -> Result<Request> {
     let mut multipart: Vec<u8> = Vec::new();
 
-    let policy = r#"{}"#;
+    let policy = format!(
+      r#"{{"expiration": "2124-01-01T00:00:00Z", "conditions": [
+      {{"bucket": "bucket"}},
+      ["starts-with", "$key", ""],
+      ["content-length-range", 0, {max_size}],
+      {{"x-amz-algorithm": "AWS4-HMAC-SHA256"}},
+      {{"x-amz-credential": "test/21240426/us-east-1/s3/aws4_request"}},
+      {{"x-amz-date": "20240426T000000Z"}}
+    ]}}"#
+    );
     let policy_b64 = BASE64_STANDARD.encode(policy);
+    let credential: AWS4Credential = "test/21240426/us-east-1/s3/aws4_request".parse()?;
+    let signature =
+      StringToSign::new(policy_b64.clone()).sign(&SecretKey::new("test", "secret"), &credential)?;
 
     // meta
     write_field(&mut multipart, "key", "test.txt");
@@ -164,11 +265,7 @@ mod test {
       "test/21240426/us-east-1/s3/aws4_request",
     );
     write_field(&mut multipart, "x-amz-date", "20240426T000000Z");
-    write_field(
-      &mut multipart,
-      "x-amz-signature",
-      "7a03f608465682aeb5bd284b7c2bfa84dd90bc5e4dc118cad4b22ec1754dfcb2",
-    );
+    write_field(&mut multipart, "x-amz-signature", &signature);
 
     // file
     write!(multipart, "--{}\r\n", BOUNDARY).unwrap();
@@ -183,6 +280,7 @@ mod test {
 
     Ok(
       Request::builder()
+        .uri(uri)
         .header(
           CONTENT_TYPE,
           format!("multipart/form-data; boundary={}", BOUNDARY),
@@ -194,17 +292,34 @@ mod test {
 
   #[tokio::test]
   async fn test_multipart_auth() -> Result<()> {
-    let req = multipart()?;
-    let auth: S3Auth<Vec<u8>> = multipart_auth(req).await?;
+    let req = multipart("http://localhost/bucket")?;
+    let auth: S3Auth<Vec<u8>> =
+      multipart_auth(req, &test_keys().await, &test_regions(), u64::MAX, false).await?;
 
-    assert_eq!(auth.identity, Identity::AccessKey("test".to_string()));
+    assert!(matches!(auth.identity, Identity::AccessKey(key) if key.id == "test"));
     assert_eq!(auth.body, b"Hello, world!".to_vec());
 
+    // the policy only allows uploads to its bucket
+    let req = multipart("http://localhost/other")?;
+    assert!(
+      multipart_auth::<Vec<u8>>(req, &test_keys().await, &test_regions(), u64::MAX, false)
+        .await
+        .is_err()
+    );
+
+    // and files up to its maximum size
+    let req = multipart_with_max_size("http://localhost/bucket", 12)?;
+    let Err(err) =
+      multipart_auth::<Vec<u8>>(req, &test_keys().await, &test_regions(), u64::MAX, false).await
+    else {
+      panic!("file larger than the policy allows was accepted");
+    };
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
     Ok(())
   }
 
-  #[tokio::test]
-  async fn test_multipart_auth_anonymous() -> Result<()> {
+  fn anonymous_form() -> Request {
     let mut multipart: Vec<u8> = Vec::new();
 
     // file
@@ -218,15 +333,26 @@ mod test {
     write!(multipart, "Hello, world!\r\n").unwrap();
     write!(multipart, "--{}--\r\n", BOUNDARY).unwrap();
 
-    let req = Request::builder()
+    Request::builder()
       .header(
         CONTENT_TYPE,
         format!("multipart/form-data; boundary={}", BOUNDARY),
       )
       .body(multipart.into())
-      .unwrap();
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_multipart_auth_anonymous() -> Result<()> {
+    let keys = test_keys().await;
+    assert!(
+      multipart_auth::<Vec<u8>>(anonymous_form(), &keys, &test_regions(), u64::MAX, false)
+        .await
+        .is_err()
+    );
 
-    let auth: S3Auth<Vec<u8>> = multipart_auth(req).await?;
+    let auth: S3Auth<Vec<u8>> =
+      multipart_auth(anonymous_form(), &keys, &test_regions(), u64::MAX, true).await?;
     assert_eq!(auth.identity, Identity::Anonymous);
     assert_eq!(auth.body, b"Hello, world!".to_vec());
     Ok(())
@@ -234,11 +360,71 @@ mod test {
 
   #[tokio::test]
   async fn test_parse_multipart() -> Result<()> {
-    let req = multipart()?;
+    let req = multipart("http://localhost/bucket")?;
     let multipart = Multipart::from_request(req, &()).await?;
 
-    let result = parse_multipart(multipart, &mut <() as BodyWriter>::new().await?).await;
-    assert!(result.is_ok());
+    let (_, data) = parse_multipart(
+      multipart,
+      &mut <() as BodyWriter>::new().await?,
+      async |fields| {
+        assert!(auth_info(fields)?.is_some());
+        Ok(((), u64::MAX))
+      },
+    )
+    .await?;
+    assert_eq!(data.fields["key"], "test.txt");
+    assert_eq!(data.size, 13);
+
+    Ok(())
+  }
+
+  fn form(fields: &[(&str, &str)]) -> Request {
+    let mut multipart: Vec<u8> = Vec::new();
+    for (key, content) in fields {
+      write_field(&mut multipart, key, content);
+    }
+    write!(multipart, "--{}--\r\n", BOUNDARY).unwrap();
+
+    Request::builder()
+      .header(
+        CONTENT_TYPE,
+        format!("multipart/form-data; boundary={}", BOUNDARY),
+      )
+      .body(multipart.into())
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_parse_multipart_limits() -> Result<()> {
+    let parse = async |req: Request, max_size: u64| {
+      let multipart = Multipart::from_request(req, &()).await?;
+      let mut writer = Vec::new();
+      parse_multipart(multipart, &mut writer, async |_| Ok(((), max_size))).await?;
+      Ok::<_, ErrorReport>(writer)
+    };
+
+    let req = form(&[("key", "test.txt"), ("file", "Hello, world!")]);
+    assert_eq!(parse(req, 13).await?, b"Hello, world!");
+
+    let req = form(&[("key", "test.txt"), ("file", "Hello, world!")]);
+    assert!(parse(req, 12).await.is_err());
+
+    let req = form(&[("file", "Hello, world!"), ("key", "test.txt")]);
+    assert!(parse(req, u64::MAX).await.is_err());
+
+    let req = form(&[("key", &"a".repeat(MAX_FIELDS_SIZE + 1)), ("file", "")]);
+    assert!(parse(req, u64::MAX).await.is_err());
+
+    // nothing of the file is read if the fields are rejected
+    let req = form(&[("key", "test.txt"), ("file", "Hello, world!")]);
+    let multipart = Multipart::from_request(req, &()).await?;
+    let mut writer = Vec::new();
+    let result = parse_multipart(multipart, &mut writer, async |_| -> Result<((), u64)> {
+      s3_bail!(AccessDenied, "Rejected")
+    })
+    .await;
+    assert!(result.is_err());
+    assert!(writer.is_empty());
 
     Ok(())
   }
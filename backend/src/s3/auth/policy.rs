@@ -0,0 +1,331 @@
+use std::{
+  collections::HashMap,
+  fmt::{self, Display},
+};
+
+use base64::prelude::*;
+use centaurus::error::Result;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::s3_bail;
+
+/// Form fields that are not covered by the conditions of a policy
+const EXEMPT_FIELDS: [&str; 3] = ["policy", "x-amz-signature", "file"];
+const IGNORE_PREFIX: &str = "x-ignore-";
+
+/// Policy of a browser form upload
+/// https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-HTTPPOSTConstructPolicy.html
+#[derive(Debug)]
+pub struct PostPolicy {
+  expiration: DateTime<Utc>,
+  conditions: Vec<Condition>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Condition {
+  /// lowercase field name and the exact value
+  Eq(String, String),
+  /// lowercase field name and the prefix of the value
+  StartsWith(String, String),
+  /// inclusive range of the file size
+  ContentLengthRange(u64, u64),
+}
+
+impl PostPolicy {
+  /// Decodes the base64 encoded policy document of a form
+  #[instrument]
+  pub fn parse(policy: &str) -> Result<Self> {
+    let Ok(document) = BASE64_STANDARD.decode(policy) else {
+      s3_bail!(
+        InvalidPolicyDocument,
+        "Invalid Policy: Invalid base64 encoding"
+      );
+    };
+    let Ok(Value::Object(document)) = serde_json::from_slice::<Value>(&document) else {
+      s3_bail!(InvalidPolicyDocument, "Invalid Policy: Invalid JSON");
+    };
+
+    let Some(expiration) = document
+      .get("expiration")
+      .and_then(Value::as_str)
+      .and_then(|expiration| DateTime::parse_from_rfc3339(expiration).ok())
+    else {
+      s3_bail!(
+        InvalidPolicyDocument,
+        "Invalid Policy: Policy missing expiration or expiration is not a valid date"
+      );
+    };
+    let Some(conditions) = document.get("conditions").and_then(Value::as_array) else {
+      s3_bail!(
+        InvalidPolicyDocument,
+        "Invalid Policy: Policy missing conditions"
+      );
+    };
+
+    Ok(Self {
+      expiration: expiration.with_timezone(&Utc),
+      conditions: conditions
+        .iter()
+        .map(Condition::parse)
+        .collect::<Result<_>>()?,
+    })
+  }
+
+  /// Checks the fields of a form upload against the policy before its file is read,
+  /// the field names of the form are lowercase
+  pub fn check_fields(&self, bucket: &str, fields: &HashMap<String, String>) -> Result<()> {
+    if self.expiration < Utc::now() {
+      s3_bail!(AccessDenied, "Invalid according to Policy: Policy expired.");
+    }
+
+    let value = |name: &str| match name {
+      "bucket" => Some(bucket),
+      name => fields.get(name).map(String::as_str),
+    };
+    for condition in &self.conditions {
+      condition.check(value)?;
+    }
+
+    for name in fields.keys() {
+      if EXEMPT_FIELDS.contains(&name.as_str()) || name.starts_with(IGNORE_PREFIX) {
+        continue;
+      }
+      if !self
+        .conditions
+        .iter()
+        .any(|condition| condition.covers(name))
+      {
+        s3_bail!(
+          AccessDenied,
+          "Invalid according to Policy: Extra input fields: {name}"
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Largest file the policy allows, enforced while the file is read
+  pub fn max_size(&self) -> Option<u64> {
+    self
+      .conditions
+      .iter()
+      .filter_map(|condition| match condition {
+        Condition::ContentLengthRange(_, max) => Some(*max),
+        _ => None,
+      })
+      .min()
+  }
+
+  /// Checks the size of the file once it was read
+  pub fn check_size(&self, size: u64) -> Result<()> {
+    for condition in &self.conditions {
+      if let Condition::ContentLengthRange(min, max) = condition {
+        if size < *min {
+          s3_bail!(
+            EntityTooSmall,
+            "Your proposed upload is smaller than the minimum allowed size"
+          );
+        }
+        if size > *max {
+          s3_bail!(
+            EntityTooLarge,
+            "Your proposed upload exceeds the maximum allowed size"
+          );
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Condition {
+  fn parse(value: &Value) -> Result<Self> {
+    match value {
+      Value::Object(condition) if condition.len() == 1 => match condition.iter().next() {
+        Some((name, Value::String(value))) => Ok(Self::Eq(field_name(name), value.clone())),
+        _ => s3_bail!(
+          InvalidPolicyDocument,
+          "Invalid Policy: Invalid Simple-Condition: value must be a string"
+        ),
+      },
+      Value::Array(condition) if condition.len() == 3 => {
+        let operator = condition[0].as_str().unwrap_or("").to_ascii_lowercase();
+        match (operator.as_str(), &condition[1], &condition[2]) {
+          ("eq", Value::String(name), Value::String(value)) if name.starts_with('$') => {
+            Ok(Self::Eq(field_name(name), value.clone()))
+          }
+          ("starts-with", Value::String(name), Value::String(prefix)) if name.starts_with('$') => {
+            Ok(Self::StartsWith(field_name(name), prefix.clone()))
+          }
+          ("content-length-range", min, max) => match (length(min), length(max)) {
+            (Some(min), Some(max)) if min <= max => Ok(Self::ContentLengthRange(min, max)),
+            _ => s3_bail!(
+              InvalidPolicyDocument,
+              "Invalid Policy: Invalid content-length-range condition"
+            ),
+          },
+          _ => s3_bail!(
+            InvalidPolicyDocument,
+            "Invalid Policy: Invalid condition {value}"
+          ),
+        }
+      }
+      _ => s3_bail!(
+        InvalidPolicyDocument,
+        "Invalid Policy: Invalid condition {value}"
+      ),
+    }
+  }
+
+  /// Checks a field condition, size conditions are checked separately
+  fn check<'a>(&self, value: impl Fn(&str) -> Option<&'a str>) -> Result<()> {
+    let matches = match self {
+      Self::Eq(name, expected) => value(name) == Some(expected.as_str()),
+      // content types may be a list, every entry has to match
+      Self::StartsWith(name, prefix) if name == "content-type" => value(name)
+        .unwrap_or("")
+        .split(',')
+        .all(|value| value.trim().starts_with(prefix.as_str())),
+      Self::StartsWith(name, prefix) => value(name).unwrap_or("").starts_with(prefix.as_str()),
+      Self::ContentLengthRange(..) => true,
+    };
+
+    if !matches {
+      s3_bail!(
+        AccessDenied,
+        "Invalid according to Policy: Policy Condition failed: {self}"
+      );
+    }
+    Ok(())
+  }
+
+  fn covers(&self, field: &str) -> bool {
+    match self {
+      Self::Eq(name, _) | Self::StartsWith(name, _) => name == field,
+      Self::ContentLengthRange(..) => false,
+    }
+  }
+}
+
+impl Display for Condition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Eq(name, value) => write!(f, r#"["eq", "${name}", "{value}"]"#),
+      Self::StartsWith(name, prefix) => write!(f, r#"["starts-with", "${name}", "{prefix}"]"#),
+      Self::ContentLengthRange(min, max) => write!(f, r#"["content-length-range", {min}, {max}]"#),
+    }
+  }
+}
+
+fn field_name(name: &str) -> String {
+  name.trim_start_matches('$').to_ascii_lowercase()
+}
+
+/// Lengths may be numbers or numeric strings
+fn length(value: &Value) -> Option<u64> {
+  match value {
+    Value::Number(number) => number.as_u64(),
+    Value::String(number) => number.parse().ok(),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+
+  use super::*;
+
+  fn policy(conditions: Value) -> PostPolicy {
+    let document = json!({
+      "expiration": "2124-01-01T00:00:00.000Z",
+      "conditions": conditions,
+    });
+    PostPolicy::parse(&BASE64_STANDARD.encode(document.to_string())).unwrap()
+  }
+
+  fn fields(fields: &[(&str, &str)]) -> HashMap<String, String> {
+    fields
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn test_parse() {
+    let policy = policy(json!([
+      {"bucket": "bucket"},
+      ["starts-with", "$Key", "user/"],
+      ["eq", "$success_action_status", "201"],
+      ["content-length-range", 1, "1024"],
+    ]));
+    assert_eq!(
+      policy.conditions,
+      vec![
+        Condition::Eq("bucket".into(), "bucket".into()),
+        Condition::StartsWith("key".into(), "user/".into()),
+        Condition::Eq("success_action_status".into(), "201".into()),
+        Condition::ContentLengthRange(1, 1024),
+      ]
+    );
+
+    for invalid in [
+      json!({"conditions": []}),
+      json!({"expiration": "tomorrow", "conditions": []}),
+      json!({"expiration": "2124-01-01T00:00:00Z"}),
+      json!({"expiration": "2124-01-01T00:00:00Z", "conditions": [["eq", "key", "a"]]}),
+      json!({"expiration": "2124-01-01T00:00:00Z", "conditions": [["content-length-range", 2, 1]]}),
+    ] {
+      assert!(PostPolicy::parse(&BASE64_STANDARD.encode(invalid.to_string())).is_err());
+    }
+  }
+
+  #[test]
+  fn test_check() {
+    let policy = policy(json!([
+      {"bucket": "bucket"},
+      ["starts-with", "$key", "user/"],
+      ["starts-with", "$content-type", "image/"],
+      ["content-length-range", 1, 1024],
+    ]));
+    let form = fields(&[
+      ("key", "user/a.png"),
+      ("content-type", "image/png"),
+      ("policy", "..."),
+    ]);
+    assert!(policy.check_fields("bucket", &form).is_ok());
+    assert!(policy.check_fields("other", &form).is_err());
+    assert!(policy.check_size(10).is_ok());
+    assert!(policy.check_size(0).is_err());
+    assert!(policy.check_size(1025).is_err());
+    assert_eq!(policy.max_size(), Some(1024));
+
+    let form = fields(&[("key", "admin/a.png"), ("content-type", "image/png")]);
+    assert!(policy.check_fields("bucket", &form).is_err());
+    let form = fields(&[("key", "user/a"), ("content-type", "image/png,text/plain")]);
+    assert!(policy.check_fields("bucket", &form).is_err());
+
+    // every field has to be covered by a condition
+    let form = fields(&[
+      ("key", "user/a.png"),
+      ("content-type", "image/png"),
+      ("acl", "public-read"),
+    ]);
+    assert!(policy.check_fields("bucket", &form).is_err());
+    let form = fields(&[
+      ("key", "user/a.png"),
+      ("content-type", "image/png"),
+      ("x-ignore-tracking", "1"),
+    ]);
+    assert!(policy.check_fields("bucket", &form).is_ok());
+  }
+
+  #[test]
+  fn test_expired() {
+    let document = json!({"expiration": "2000-01-01T00:00:00Z", "conditions": []});
+    let policy = PostPolicy::parse(&BASE64_STANDARD.encode(document.to_string())).unwrap();
+    assert!(policy.check_fields("bucket", &HashMap::new()).is_err());
+  }
+}
@@ -0,0 +1,570 @@
+use std::{
+  fmt, io,
+  pin::Pin,
+  task::{Context, Poll, ready},
+};
+
+use axum::body::{Body as AxumBody, BodyDataStream, Bytes};
+use centaurus::{anyhow, error::Result};
+use chrono::{DateTime, Utc};
+use eyre::OptionExt;
+use futures::StreamExt;
+use http::HeaderMap;
+use memchr::memchr;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::{
+  s3::{
+    auth::{
+      access_key::SecretKey,
+      body::{Body, BodyWriter},
+      credential::AWS4Credential,
+      sig_v4::{StringToSign, signature_matches},
+    },
+    checksum::{BodyChecksums, Checksum},
+    error::{BodyError, body_error},
+    interface::ObjectReader,
+  },
+  s3_bail,
+};
+
+/// Name of the trailer that signs the other trailers
+const TRAILER_SIGNATURE: &str = "x-amz-trailer-signature";
+/// Longest chunk header or trailer line, chunk signatures are far shorter
+const MAX_LINE_LENGTH: usize = 4096;
+/// Largest chunk, a signed chunk is kept in memory until its signature is verified
+const MAX_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Request body that is verified while it is read
+///
+/// Chunk signatures are verified before the data of a chunk is returned, everything
+/// that can only be verified at the end of the body fails the last read instead,
+/// so a storage writing the stream discards what it wrote
+pub struct BodyStream {
+  body: BodyDataStream,
+  decoder: Option<Decoder>,
+  pending: Bytes,
+  checksum: Option<Checksum>,
+}
+
+impl fmt::Debug for BodyStream {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("BodyStream")
+      .field("finished", &self.decoder.is_none())
+      .finish_non_exhaustive()
+  }
+}
+
+impl BodyStream {
+  pub(super) fn new(body: AxumBody, decoder: Decoder) -> Self {
+    Self {
+      body: body.into_data_stream(),
+      decoder: Some(decoder),
+      pending: Bytes::new(),
+      checksum: None,
+    }
+  }
+
+  /// Reads the whole body into a writer, returns the checksum of the body
+  pub(super) async fn write_to(mut self, writer: &mut impl BodyWriter) -> Result<Option<Checksum>> {
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+      let read = self
+        .read(&mut buf)
+        .await
+        .map_err(|e| body_error(e.into()))?;
+      if read == 0 {
+        return Ok(self.checksum);
+      }
+      writer.write(&buf[..read]).await?;
+    }
+  }
+
+  fn poll_decode(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    loop {
+      if !self.pending.is_empty() {
+        return Poll::Ready(Ok(()));
+      }
+      let Some(decoder) = &mut self.decoder else {
+        return Poll::Ready(Ok(()));
+      };
+
+      match ready!(self.body.poll_next_unpin(cx)) {
+        Some(Ok(data)) => self.pending = decoder.decode(data)?,
+        Some(Err(e)) => {
+          return Poll::Ready(Err(anyhow!(
+            INTERNAL_SERVER_ERROR,
+            "Error reading body: {}",
+            e
+          )));
+        }
+        None => {
+          let decoder = self.decoder.take().ok_or_eyre("Body already finished")?;
+          self.checksum = decoder.finish()?;
+        }
+      }
+    }
+  }
+}
+
+impl AsyncRead for BodyStream {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if let Err(e) = ready!(this.poll_decode(cx)) {
+      // a failed body can not be continued
+      this.decoder = None;
+      return Poll::Ready(Err(BodyError(e).into()));
+    }
+
+    let read = this.pending.len().min(buf.remaining());
+    buf.put_slice(&this.pending.split_to(read));
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl ObjectReader for BodyStream {
+  fn checksum(&self) -> Option<Checksum> {
+    self.checksum.clone()
+  }
+}
+
+/// The body is streamed to the handler which has to read it to the end,
+/// bodies of form uploads were already received and verified
+#[async_trait::async_trait]
+impl Body for BodyStream {
+  type Writer = Vec<u8>;
+
+  async fn from_writer(writer: Self::Writer) -> Result<Self> {
+    Ok(Self::new(
+      AxumBody::from(writer),
+      Decoder::new(Framing::Plain(None), BodyChecksums::new(&HeaderMap::new())?),
+    ))
+  }
+
+  async fn from_stream(stream: BodyStream) -> Result<(Self, Option<Checksum>)> {
+    Ok((stream, None))
+  }
+}
+
+/// Verification of a request body in the framing it was sent in
+pub(super) struct Decoder {
+  framing: Framing,
+  checksums: BodyChecksums,
+}
+
+pub(super) enum Framing {
+  /// body sent as is, with the sha256 the request was signed with
+  Plain(Option<(Sha256, String)>),
+  /// body sent in aws-chunked encoding
+  Chunked(Box<ChunkDecoder>),
+}
+
+impl Decoder {
+  pub(super) fn new(framing: Framing, checksums: BodyChecksums) -> Self {
+    Self { framing, checksums }
+  }
+
+  fn decode(&mut self, data: Bytes) -> Result<Bytes> {
+    let data = match &mut self.framing {
+      Framing::Plain(sha256) => {
+        if let Some((sha256, _)) = sha256 {
+          sha256.update(&data);
+        }
+        data
+      }
+      Framing::Chunked(chunks) => chunks.decode(&data)?,
+    };
+    self.checksums.update(&data);
+    Ok(data)
+  }
+
+  fn finish(mut self) -> Result<Option<Checksum>> {
+    match self.framing {
+      Framing::Plain(Some((sha256, expected))) => {
+        if hex::encode(sha256.finalize()) != expected {
+          s3_bail!(
+            XAmzContentSha256Mismatch,
+            "The provided 'x-amz-content-sha256' header does not match what was computed"
+          );
+        }
+      }
+      Framing::Plain(None) => {}
+      Framing::Chunked(chunks) => chunks.finish(&mut self.checksums)?,
+    }
+    self.checksums.finish()
+  }
+}
+
+/// Signing context of a chunked body, every chunk signature covers the previous one
+pub(super) struct ChunkSigner {
+  credential: AWS4Credential,
+  secret_key: SecretKey,
+  datetime: DateTime<Utc>,
+  previous_signature: String,
+}
+
+impl ChunkSigner {
+  pub(super) fn new(
+    credential: AWS4Credential,
+    secret_key: SecretKey,
+    datetime: DateTime<Utc>,
+    seed_signature: String,
+  ) -> Self {
+    Self {
+      credential,
+      secret_key,
+      datetime,
+      previous_signature: seed_signature,
+    }
+  }
+
+  fn verify_chunk(&mut self, data: &[u8], expected: &str) -> Result<()> {
+    let signature = StringToSign::chunked(
+      &self.datetime,
+      &self.credential,
+      &self.previous_signature,
+      data,
+    )
+    .sign(&self.secret_key, &self.credential)?;
+    if !signature_matches(&signature, expected) {
+      s3_bail!(SignatureDoesNotMatch, "Chunk signature mismatch");
+    }
+    self.previous_signature = signature;
+    Ok(())
+  }
+
+  fn verify_trailer(&self, headers: &[(String, String)], expected: &str) -> Result<()> {
+    let signature = StringToSign::chunked_trailer(
+      &self.datetime,
+      &self.credential,
+      &self.previous_signature,
+      headers,
+    )
+    .sign(&self.secret_key, &self.credential)?;
+    if !signature_matches(&signature, expected) {
+      s3_bail!(SignatureDoesNotMatch, "Trailer signature mismatch");
+    }
+    Ok(())
+  }
+}
+
+/// Incremental decoder of the aws-chunked encoding
+///
+/// Without a signer the chunks are unsigned, like with `STREAMING-UNSIGNED-PAYLOAD-TRAILER`
+pub(super) struct ChunkDecoder {
+  signer: Option<ChunkSigner>,
+  buffer: Vec<u8>,
+  state: ChunkState,
+  /// decoded length announced in `x-amz-decoded-content-length`
+  length: u64,
+  decoded: u64,
+  /// the body ends with a trailer of headers
+  trailer: bool,
+  trailers: Vec<(String, String)>,
+  trailer_signature: Option<String>,
+}
+
+#[derive(Debug)]
+enum ChunkState {
+  Header,
+  Data(ChunkMeta),
+  /// the data of an unsigned chunk was passed on, it ends with \r\n
+  DataEnd,
+  /// the last chunk was read, the trailer ends with an empty line
+  Trailer,
+  End,
+}
+
+#[derive(Debug)]
+struct ChunkMeta {
+  /// length of the data, of unsigned chunks only what is left of it
+  length: usize,
+  signature: Option<String>,
+}
+
+impl ChunkDecoder {
+  pub(super) fn new(signer: Option<ChunkSigner>, length: u64, trailer: bool) -> Self {
+    Self {
+      signer,
+      buffer: Vec::new(),
+      state: ChunkState::Header,
+      length,
+      decoded: 0,
+      trailer,
+      trailers: Vec::new(),
+      trailer_signature: None,
+    }
+  }
+
+  /// Returns the data of all signed chunks that were completed by `data`, the data
+  /// of unsigned chunks is returned as it arrives
+  fn decode(&mut self, data: &[u8]) -> Result<Bytes> {
+    self.buffer.extend_from_slice(data);
+    let mut decoded = Vec::new();
+    loop {
+      match &mut self.state {
+        ChunkState::Header => {
+          let Some(line) = self.line()? else {
+            break;
+          };
+          let mut parts = line.split(';');
+          let Some(length) = parts
+            .next()
+            .and_then(|s| usize::from_str_radix(s.trim(), 16).ok())
+          else {
+            s3_bail!(IncompleteBody, "Invalid chunk length");
+          };
+          if length > MAX_CHUNK_SIZE {
+            s3_bail!(
+              InvalidArgument,
+              "Chunks can not be larger than {MAX_CHUNK_SIZE} bytes"
+            );
+          }
+          let signature = parts
+            .find_map(|s| s.trim().strip_prefix("chunk-signature="))
+            .map(|s| s.to_string());
+          if self.signer.is_some() && signature.is_none() {
+            s3_bail!(IncompleteBody, "Missing chunk signature");
+          }
+
+          self.state = ChunkState::Data(ChunkMeta { length, signature });
+        }
+        // the last chunk has no data, its header is followed by the trailer
+        ChunkState::Data(meta) if meta.length == 0 => {
+          if let Some(signer) = &mut self.signer {
+            signer.verify_chunk(&[], meta.signature.as_deref().unwrap_or_default())?;
+          }
+          self.state = ChunkState::Trailer;
+        }
+        ChunkState::Data(meta) if self.signer.is_none() => {
+          let length = meta.length.min(self.buffer.len());
+          decoded.extend(self.buffer.drain(..length));
+          self.decoded += length as u64;
+          meta.length -= length;
+          if meta.length > 0 {
+            break;
+          }
+          self.state = ChunkState::DataEnd;
+        }
+        ChunkState::DataEnd => {
+          if self.buffer.len() < 2 {
+            break;
+          }
+          if &self.buffer[..2] != b"\r\n" {
+            s3_bail!(IncompleteBody, "Invalid chunk ending");
+          }
+          self.buffer.drain(..2);
+          self.state = ChunkState::Header;
+        }
+        ChunkState::Data(meta) => {
+          // +2 for trailing \r\n, the length is limited so this can not overflow
+          let end = meta.length + 2;
+          if self.buffer.len() < end {
+            break;
+          }
+          if &self.buffer[meta.length..end] != b"\r\n" {
+            s3_bail!(IncompleteBody, "Invalid chunk ending");
+          }
+
+          let length = meta.length;
+          let data = &self.buffer[..length];
+          if let Some(signer) = &mut self.signer {
+            signer.verify_chunk(data, meta.signature.as_deref().unwrap_or_default())?;
+          }
+          decoded.extend_from_slice(data);
+          self.decoded += length as u64;
+          // remove the data with its trailing \r\n
+          self.buffer.drain(..end);
+          self.state = ChunkState::Header;
+        }
+        ChunkState::Trailer => {
+          let Some(line) = self.line()? else {
+            break;
+          };
+          if line.is_empty() {
+            self.state = ChunkState::End;
+            continue;
+          }
+          if !self.trailer || self.trailer_signature.is_some() {
+            s3_bail!(MalformedTrailerError, "Unexpected trailer {line}");
+          }
+
+          let Some((name, value)) = line.split_once(':') else {
+            s3_bail!(MalformedTrailerError, "Invalid trailer header");
+          };
+          let name = name.trim().to_ascii_lowercase();
+          let value = value.trim().to_string();
+          if name == TRAILER_SIGNATURE {
+            self.trailer_signature = Some(value);
+          } else {
+            self.trailers.push((name, value));
+          }
+        }
+        ChunkState::End => {
+          if !self.buffer.is_empty() {
+            s3_bail!(IncompleteBody, "Unexpected data after the last chunk");
+          }
+          break;
+        }
+      }
+    }
+    Ok(Bytes::from(decoded))
+  }
+
+  /// Removes the next line from the buffer, chunk headers and trailers end with \r\n
+  fn line(&mut self) -> Result<Option<String>> {
+    let Some(i) = memchr(b'\n', &self.buffer) else {
+      if self.buffer.len() > MAX_LINE_LENGTH {
+        s3_bail!(IncompleteBody, "Chunk header or trailer is too long");
+      }
+      return Ok(None);
+    };
+    let Ok(line) = str::from_utf8(&self.buffer[..i]) else {
+      s3_bail!(IncompleteBody, "Invalid chunk header or trailer");
+    };
+    let line = line.trim_end_matches('\r').to_string();
+    self.buffer.drain(..=i);
+    Ok(Some(line))
+  }
+
+  fn finish(self, checksums: &mut BodyChecksums) -> Result<()> {
+    if self.decoded != self.length || !matches!(self.state, ChunkState::End) {
+      s3_bail!(IncompleteBody, "Decoded content length mismatch");
+    }
+
+    if self.trailer
+      && let Some(signer) = &self.signer
+    {
+      let Some(signature) = &self.trailer_signature else {
+        s3_bail!(MalformedTrailerError, "Missing trailer signature");
+      };
+      signer.verify_trailer(&self.trailers, signature)?;
+    }
+
+    for (name, value) in &self.trailers {
+      checksums.trailer(name, value)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use http::StatusCode;
+
+  use super::*;
+  use crate::s3::error::{S3Error, S3ErrorCode};
+
+  fn plain(body: &'static str, sha256: &str) -> BodyStream {
+    BodyStream::new(
+      AxumBody::from(body),
+      Decoder::new(
+        Framing::Plain(Some((Sha256::new(), sha256.to_string()))),
+        BodyChecksums::new(&HeaderMap::new()).unwrap(),
+      ),
+    )
+  }
+
+  #[tokio::test]
+  async fn test_sha256() {
+    let mut writer = Vec::new();
+    plain(
+      "hello",
+      "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+    )
+    .write_to(&mut writer)
+    .await
+    .unwrap();
+    assert_eq!(writer, b"hello");
+
+    let err = plain("hello", &"0".repeat(64))
+      .write_to(&mut Vec::new())
+      .await
+      .unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+  }
+
+  /// Decodes an unsigned chunked body sent in pieces of `split` bytes
+  async fn unsigned_chunks(body: &str, split: usize) -> Result<(Vec<u8>, Option<Checksum>)> {
+    let headers = HeaderMap::from_iter([(
+      "x-amz-trailer".parse().unwrap(),
+      "x-amz-checksum-crc32".parse().unwrap(),
+    )]);
+    let mut decoder = Decoder::new(
+      Framing::Chunked(Box::new(ChunkDecoder::new(None, 13, true))),
+      BodyChecksums::new(&headers)?,
+    );
+    let mut decoded = Vec::new();
+    for piece in body.as_bytes().chunks(split) {
+      decoded.extend_from_slice(&decoder.decode(Bytes::copy_from_slice(piece))?);
+    }
+    Ok((decoded, decoder.finish()?))
+  }
+
+  #[tokio::test]
+  async fn test_unsigned_trailer() {
+    let body = "b\r\nHello, worl\r\n2\r\nd!\r\n0\r\n\
+      x-amz-checksum-crc32:6+bG5g==\r\nx-amz-meta-other:value\r\n\r\n";
+    for split in [1, 7, body.len()] {
+      let (decoded, checksum) = unsigned_chunks(body, split).await.unwrap();
+      assert_eq!(decoded, b"Hello, world!");
+      assert!(checksum.is_some());
+    }
+
+    // unsigned data is passed on before its chunk is complete
+    let mut decoder = ChunkDecoder::new(None, 13, true);
+    assert_eq!(decoder.decode(b"b\r\nHello").unwrap(), b"Hello"[..]);
+    assert_eq!(decoder.decode(b", worl\r").unwrap(), b", worl"[..]);
+    assert!(decoder.decode(b"\n2\r\n").unwrap().is_empty());
+    assert!(decoder.decode(b"d!xx").is_err());
+
+    let wrong = body.replace("6+bG5g==", "AAAAAA==");
+    assert!(unsigned_chunks(&wrong, 7).await.is_err());
+    let missing = "b\r\nHello, worl\r\n2\r\nd!\r\n0\r\n\r\n";
+    assert!(unsigned_chunks(missing, 7).await.is_err());
+    let incomplete = body.trim_end_matches("\r\n");
+    assert!(unsigned_chunks(incomplete, 7).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_chunk_too_large() {
+    let huge = format!("{:x}\r\n", MAX_CHUNK_SIZE + 1);
+    let err = unsigned_chunks(&huge, huge.len()).await.unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    let overflow = format!("{:x}\r\n", usize::MAX);
+    assert!(unsigned_chunks(&overflow, overflow.len()).await.is_err());
+  }
+
+  async fn error_code(body: &str) -> Option<S3ErrorCode> {
+    let err = unsigned_chunks(body, body.len()).await.unwrap_err();
+    err.error.downcast_ref::<S3Error>().map(|error| error.code)
+  }
+
+  #[tokio::test]
+  async fn test_malformed_framing() {
+    assert_eq!(
+      error_code("zz\r\nHello").await,
+      Some(S3ErrorCode::IncompleteBody)
+    );
+    // the trailer framing of unsigned bodies is checked as well
+    assert_eq!(
+      error_code("b\r\nHello, worl\r\n2\r\nd!\r\n0\r\nno header\r\n\r\n").await,
+      Some(S3ErrorCode::MalformedTrailerError)
+    );
+  }
+
+  #[tokio::test]
+  async fn test_fails_last_read() {
+    // the data is returned before the body can be verified
+    let mut stream = plain("hello", &"0".repeat(64));
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    let err = stream.read(&mut buf).await.unwrap_err();
+    assert!(err.get_ref().is_some_and(|e| e.is::<BodyError>()));
+  }
+}
@@ -0,0 +1,362 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use centaurus::error::Result;
+use http::{HeaderMap, header::HeaderName};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::Sha256;
+
+use crate::s3_bail;
+
+const CONTENT_MD5: &str = "content-md5";
+const SDK_CHECKSUM_ALGORITHM: &str = "x-amz-sdk-checksum-algorithm";
+const CHECKSUM_ALGORITHM: &str = "x-amz-checksum-algorithm";
+const TRAILER: &str = "x-amz-trailer";
+
+/// Algorithms of the flexible `x-amz-checksum-*` checksums
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ChecksumAlgorithm {
+  Crc32,
+  Crc32c,
+  Sha1,
+  Sha256,
+}
+
+impl ChecksumAlgorithm {
+  const ALL: [ChecksumAlgorithm; 4] = [Self::Crc32, Self::Crc32c, Self::Sha1, Self::Sha256];
+
+  fn name(self) -> &'static str {
+    match self {
+      Self::Crc32 => "CRC32",
+      Self::Crc32c => "CRC32C",
+      Self::Sha1 => "SHA1",
+      Self::Sha256 => "SHA256",
+    }
+  }
+
+  /// Header carrying the base64 encoded checksum
+  pub fn header(self) -> HeaderName {
+    HeaderName::from_static(match self {
+      Self::Crc32 => "x-amz-checksum-crc32",
+      Self::Crc32c => "x-amz-checksum-crc32c",
+      Self::Sha1 => "x-amz-checksum-sha1",
+      Self::Sha256 => "x-amz-checksum-sha256",
+    })
+  }
+
+  fn from_name(name: &str) -> Option<Self> {
+    Self::ALL
+      .into_iter()
+      .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name.trim()))
+  }
+
+  fn from_header(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|algorithm| {
+      algorithm
+        .header()
+        .as_str()
+        .eq_ignore_ascii_case(name.trim())
+    })
+  }
+
+  fn digest_len(self) -> usize {
+    match self {
+      Self::Crc32 | Self::Crc32c => 4,
+      Self::Sha1 => 20,
+      Self::Sha256 => 32,
+    }
+  }
+}
+
+/// Checksum of an object as reported by the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checksum {
+  pub algorithm: ChecksumAlgorithm,
+  /// base64 encoded digest
+  pub value: String,
+}
+
+enum Hasher {
+  Crc32(crc32fast::Hasher),
+  Crc32c(u32),
+  Sha1(Sha1),
+  Sha256(Sha256),
+}
+
+impl Hasher {
+  fn new(algorithm: ChecksumAlgorithm) -> Self {
+    match algorithm {
+      ChecksumAlgorithm::Crc32 => Self::Crc32(crc32fast::Hasher::new()),
+      ChecksumAlgorithm::Crc32c => Self::Crc32c(0),
+      ChecksumAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+      ChecksumAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+    }
+  }
+
+  fn update(&mut self, data: &[u8]) {
+    match self {
+      Self::Crc32(hasher) => hasher.update(data),
+      Self::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+      Self::Sha1(hasher) => hasher.update(data),
+      Self::Sha256(hasher) => hasher.update(data),
+    }
+  }
+
+  fn finalize(self) -> String {
+    let digest = match self {
+      Self::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+      Self::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+      Self::Sha1(hasher) => hasher.finalize().to_vec(),
+      Self::Sha256(hasher) => hasher.finalize().to_vec(),
+    };
+    BASE64_STANDARD.encode(digest)
+  }
+}
+
+/// Checksums a request body is verified against while it is received
+///
+/// The expected value comes from `Content-MD5`, a `x-amz-checksum-*` header or
+/// the trailer named by `x-amz-trailer`, with only `x-amz-sdk-checksum-algorithm`
+/// the checksum is computed and stored without verification
+pub struct BodyChecksums {
+  md5: Option<(Md5, String)>,
+  checksum: Option<ExpectedChecksum>,
+}
+
+struct ExpectedChecksum {
+  algorithm: ChecksumAlgorithm,
+  hasher: Hasher,
+  value: Option<String>,
+  /// the value is sent in the trailer after the body
+  trailer: bool,
+}
+
+impl BodyChecksums {
+  pub fn new(headers: &HeaderMap) -> Result<Self> {
+    let header = |name: &str| -> Result<Option<&str>> {
+      match headers.get(name).map(|value| value.to_str()) {
+        Some(Ok(value)) => Ok(Some(value.trim())),
+        Some(Err(_)) => s3_bail!(InvalidArgument, "Invalid {name} header"),
+        None => Ok(None),
+      }
+    };
+
+    let md5 = match header(CONTENT_MD5)? {
+      Some(value) => {
+        if !BASE64_STANDARD
+          .decode(value)
+          .is_ok_and(|digest| digest.len() == 16)
+        {
+          s3_bail!(InvalidDigest, "The Content-MD5 you specified is not valid");
+        }
+        Some((Md5::new(), value.to_string()))
+      }
+      None => None,
+    };
+
+    let mut checksum = None;
+    for algorithm in ChecksumAlgorithm::ALL {
+      let Some(value) = header(algorithm.header().as_str())? else {
+        continue;
+      };
+      if checksum.is_some() {
+        s3_bail!(
+          InvalidRequest,
+          "Expecting a single x-amz-checksum- header, multiple checksum types are not allowed"
+        );
+      }
+      if !BASE64_STANDARD
+        .decode(value)
+        .is_ok_and(|digest| digest.len() == algorithm.digest_len())
+      {
+        s3_bail!(
+          InvalidRequest,
+          "Value for {} header is invalid",
+          algorithm.header()
+        );
+      }
+      checksum = Some((algorithm, Some(value.to_string()), false));
+    }
+
+    if let Some(trailer) = header(TRAILER)?
+      && let Some(algorithm) = ChecksumAlgorithm::from_header(trailer)
+    {
+      if checksum.is_some() {
+        s3_bail!(
+          InvalidRequest,
+          "Expecting a single x-amz-checksum- header, multiple checksum types are not allowed"
+        );
+      }
+      checksum = Some((algorithm, None, true));
+    }
+
+    if let Some(name) = header(SDK_CHECKSUM_ALGORITHM)?.or(header(CHECKSUM_ALGORITHM)?) {
+      let Some(algorithm) = ChecksumAlgorithm::from_name(name) else {
+        s3_bail!(InvalidRequest, "Checksum algorithm {name} is not supported");
+      };
+      match &checksum {
+        Some((expected, ..)) if *expected != algorithm => s3_bail!(
+          InvalidRequest,
+          "Value for x-amz-sdk-checksum-algorithm header is invalid"
+        ),
+        Some(_) => {}
+        None => checksum = Some((algorithm, None, false)),
+      }
+    }
+
+    Ok(Self {
+      md5,
+      checksum: checksum.map(|(algorithm, value, trailer)| ExpectedChecksum {
+        algorithm,
+        hasher: Hasher::new(algorithm),
+        value,
+        trailer,
+      }),
+    })
+  }
+
+  pub fn update(&mut self, data: &[u8]) {
+    if let Some((md5, _)) = &mut self.md5 {
+      md5.update(data);
+    }
+    if let Some(checksum) = &mut self.checksum {
+      checksum.hasher.update(data);
+    }
+  }
+
+  /// Records a header of the trailer that follows the body
+  pub fn trailer(&mut self, name: &str, value: &str) -> Result<()> {
+    let Some(algorithm) = ChecksumAlgorithm::from_header(name) else {
+      return Ok(());
+    };
+    match &mut self.checksum {
+      Some(checksum) if checksum.trailer && checksum.algorithm == algorithm => {
+        checksum.value = Some(value.trim().to_string());
+        Ok(())
+      }
+      _ => s3_bail!(
+        MalformedTrailerError,
+        "The trailer {name} was not announced in x-amz-trailer"
+      ),
+    }
+  }
+
+  /// Verifies the received body, returns the checksum that should be stored with it
+  pub fn finish(self) -> Result<Option<Checksum>> {
+    if let Some((md5, expected)) = self.md5
+      && BASE64_STANDARD.encode(md5.finalize()) != expected
+    {
+      s3_bail!(
+        BadDigest,
+        "The Content-MD5 you specified did not match what we received"
+      );
+    }
+
+    let Some(checksum) = self.checksum else {
+      return Ok(None);
+    };
+    if checksum.trailer && checksum.value.is_none() {
+      s3_bail!(
+        MalformedTrailerError,
+        "The trailer {} announced in x-amz-trailer is missing",
+        checksum.algorithm.header()
+      );
+    }
+
+    let value = checksum.hasher.finalize();
+    if checksum.value.is_some_and(|expected| expected != value) {
+      s3_bail!(
+        BadDigest,
+        "The {} you specified did not match the calculated checksum",
+        checksum.algorithm.header()
+      );
+    }
+
+    Ok(Some(Checksum {
+      algorithm: checksum.algorithm,
+      value,
+    }))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use http::StatusCode;
+
+  use super::*;
+
+  fn body_checksums(headers: &[(&'static str, &'static str)]) -> Result<BodyChecksums> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+      map.insert(*name, value.parse().unwrap());
+    }
+    BodyChecksums::new(&map)
+  }
+
+  fn verify(headers: &[(&'static str, &'static str)]) -> Result<Option<Checksum>> {
+    let mut checksums = body_checksums(headers)?;
+    checksums.update(b"hel");
+    checksums.update(b"lo");
+    checksums.finish()
+  }
+
+  #[test]
+  fn test_algorithms() {
+    for (header, value) in [
+      ("x-amz-checksum-crc32", "NhCmhg=="),
+      ("x-amz-checksum-crc32c", "mnG7TA=="),
+      ("x-amz-checksum-sha1", "qvTGHdzF6KLavt4PO0gs2a6pQ00="),
+      (
+        "x-amz-checksum-sha256",
+        "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=",
+      ),
+    ] {
+      let checksum = verify(&[(header, value)]).unwrap().unwrap();
+      assert_eq!(checksum.algorithm.header(), header);
+      assert_eq!(checksum.value, value);
+    }
+  }
+
+  #[test]
+  fn test_mismatch() {
+    let err = verify(&[("x-amz-checksum-crc32", "AAAAAA==")]).unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    assert!(verify(&[("content-md5", "XUFAKrxLKna5cZ2REBfFkg==")]).is_ok());
+    assert!(verify(&[("content-md5", "AAAAAAAAAAAAAAAAAAAAAA==")]).is_err());
+    // digests of the wrong length are rejected before the body is read
+    assert!(body_checksums(&[("content-md5", "AAAA")]).is_err());
+    assert!(body_checksums(&[("x-amz-checksum-sha1", "NhCmhg==")]).is_err());
+    assert!(
+      body_checksums(&[
+        ("x-amz-checksum-crc32", "NhCmhg=="),
+        ("x-amz-sdk-checksum-algorithm", "SHA256"),
+      ])
+      .is_err()
+    );
+  }
+
+  #[test]
+  fn test_algorithm_only() {
+    let checksum = verify(&[("x-amz-sdk-checksum-algorithm", "crc32")])
+      .unwrap()
+      .unwrap();
+    assert_eq!(checksum.value, "NhCmhg==");
+    assert_eq!(verify(&[]).unwrap(), None);
+  }
+
+  #[test]
+  fn test_trailer() {
+    let mut checksums = body_checksums(&[("x-amz-trailer", "x-amz-checksum-crc32")]).unwrap();
+    checksums.update(b"hello");
+    checksums
+      .trailer("x-amz-checksum-crc32", "NhCmhg==")
+      .unwrap();
+    assert_eq!(checksums.finish().unwrap().unwrap().value, "NhCmhg==");
+
+    let mut checksums = body_checksums(&[("x-amz-trailer", "x-amz-checksum-crc32")]).unwrap();
+    checksums.update(b"hello");
+    assert!(checksums.trailer("x-amz-checksum-sha1", "x").is_err());
+    assert!(checksums.finish().is_err());
+  }
+}
@@ -0,0 +1,400 @@
+use std::fmt::{self, Display};
+
+use axum::{
+  body::{Body, to_bytes},
+  extract::Request,
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use centaurus::{error::ErrorReport, req::xml::Xml};
+use http::{HeaderValue, StatusCode};
+use serde::Serialize;
+use serde_enum_str::Serialize_enum_str;
+use tracing::instrument;
+use uuid::Uuid;
+
+pub const AWZ_REQUEST_ID: &str = "x-amz-request-id";
+/// rejection bodies of axum extractors are short, longer bodies are not used as message
+const MAX_MESSAGE_BODY: usize = 4096;
+const INTERNAL_ERROR_MESSAGE: &str = "We encountered an internal error. Please try again.";
+
+/// Returns early with an [`S3Error`] converted into the expected error type
+#[macro_export]
+macro_rules! s3_bail {
+  ($code:ident, $($arg:tt)*) => {
+    return Err(
+      $crate::s3::error::S3Error::new($crate::s3::error::S3ErrorCode::$code, format!($($arg)*))
+        .into(),
+    )
+  };
+}
+
+pub type Result<T> = std::result::Result<T, S3ErrorResponse>;
+
+/// Error codes from https://docs.aws.amazon.com/AmazonS3/latest/API/ErrorResponses.html
+#[derive(Serialize_enum_str, Debug, Clone, Copy, PartialEq)]
+pub enum S3ErrorCode {
+  AccessDenied,
+  AuthorizationHeaderMalformed,
+  AuthorizationQueryParametersError,
+  BadDigest,
+  BucketAlreadyExists,
+  BucketAlreadyOwnedByYou,
+  BucketNotEmpty,
+  EntityTooLarge,
+  EntityTooSmall,
+  IncompleteBody,
+  InternalError,
+  InvalidAccessKeyId,
+  InvalidArgument,
+  InvalidBucketName,
+  InvalidDigest,
+  InvalidLocationConstraint,
+  InvalidPart,
+  InvalidPartNumber,
+  InvalidPartOrder,
+  InvalidPolicyDocument,
+  InvalidRange,
+  InvalidRequest,
+  KeyTooLongError,
+  MalformedTrailerError,
+  MaxPostPreDataLengthExceededError,
+  #[serde(rename = "MalformedXML")]
+  MalformedXml,
+  MethodNotAllowed,
+  MissingContentLength,
+  NoSuchBucket,
+  NoSuchKey,
+  NoSuchUpload,
+  NoSuchVersion,
+  /// generic code of 404 responses that were not raised with a specific code, like
+  /// for unknown routes, S3 reports it for HEAD requests of missing resources
+  NotFound,
+  NotImplemented,
+  PreconditionFailed,
+  ServiceUnavailable,
+  SignatureDoesNotMatch,
+  #[serde(rename = "XAmzContentSHA256Mismatch")]
+  XAmzContentSha256Mismatch,
+}
+
+impl S3ErrorCode {
+  pub fn status(self) -> StatusCode {
+    use S3ErrorCode::*;
+    match self {
+      AccessDenied | InvalidAccessKeyId | SignatureDoesNotMatch => StatusCode::FORBIDDEN,
+      NoSuchBucket | NoSuchKey | NoSuchUpload | NoSuchVersion | NotFound => StatusCode::NOT_FOUND,
+      BucketAlreadyExists | BucketAlreadyOwnedByYou | BucketNotEmpty => StatusCode::CONFLICT,
+      MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+      MissingContentLength => StatusCode::LENGTH_REQUIRED,
+      PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+      InvalidRange | InvalidPartNumber => StatusCode::RANGE_NOT_SATISFIABLE,
+      InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+      NotImplemented => StatusCode::NOT_IMPLEMENTED,
+      ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+      AuthorizationHeaderMalformed
+      | AuthorizationQueryParametersError
+      | BadDigest
+      | EntityTooLarge
+      | EntityTooSmall
+      | IncompleteBody
+      | InvalidArgument
+      | InvalidBucketName
+      | InvalidDigest
+      | InvalidLocationConstraint
+      | InvalidPart
+      | InvalidPartOrder
+      | InvalidPolicyDocument
+      | InvalidRequest
+      | KeyTooLongError
+      | MalformedTrailerError
+      | MaxPostPreDataLengthExceededError
+      | MalformedXml
+      | XAmzContentSha256Mismatch => StatusCode::BAD_REQUEST,
+    }
+  }
+
+  /// Code for errors that were not raised with a specific S3 code
+  fn from_status(status: StatusCode) -> Self {
+    use S3ErrorCode::*;
+    match status {
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AccessDenied,
+      StatusCode::NOT_FOUND => NotFound,
+      StatusCode::METHOD_NOT_ALLOWED => MethodNotAllowed,
+      StatusCode::LENGTH_REQUIRED => MissingContentLength,
+      StatusCode::PRECONDITION_FAILED => PreconditionFailed,
+      StatusCode::RANGE_NOT_SATISFIABLE => InvalidRange,
+      StatusCode::NOT_IMPLEMENTED => NotImplemented,
+      StatusCode::SERVICE_UNAVAILABLE => ServiceUnavailable,
+      status if status.is_server_error() => InternalError,
+      _ => InvalidRequest,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct S3Error {
+  pub code: S3ErrorCode,
+  pub message: String,
+  /// region the request is expected to be signed for
+  pub region: Option<String>,
+}
+
+impl S3Error {
+  pub fn new(code: S3ErrorCode, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+      region: None,
+    }
+  }
+
+  pub fn with_region(mut self, region: &str) -> Self {
+    self.region = Some(region.into());
+    self
+  }
+}
+
+impl Display for S3Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}: {}", self.code, self.message)
+  }
+}
+
+impl std::error::Error for S3Error {}
+
+impl From<S3Error> for ErrorReport {
+  #[track_caller]
+  fn from(value: S3Error) -> Self {
+    let status = value.code.status();
+    ErrorReport::new(eyre::Report::new(value), status)
+  }
+}
+
+/// Error that ended the stream of a request body, it passes the storage as
+/// [`std::io::Error`] and is reported like the original error
+#[derive(Debug)]
+pub struct BodyError(pub ErrorReport);
+
+impl Display for BodyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0.error)
+  }
+}
+
+impl std::error::Error for BodyError {}
+
+impl From<BodyError> for std::io::Error {
+  fn from(value: BodyError) -> Self {
+    std::io::Error::other(value)
+  }
+}
+
+/// Restores the original error of a request body that failed while it was streamed
+pub fn body_error(report: ErrorReport) -> ErrorReport {
+  let is_body_error = report
+    .error
+    .downcast_ref::<std::io::Error>()
+    .and_then(|e| e.get_ref())
+    .is_some_and(|e| e.is::<BodyError>());
+  if !is_body_error {
+    return report;
+  }
+
+  match report
+    .error
+    .downcast::<std::io::Error>()
+    .ok()
+    .and_then(|e| e.into_inner())
+    .and_then(|e| e.downcast::<BodyError>().ok())
+  {
+    Some(error) => error.0,
+    None => unreachable!("checked to be a body error"),
+  }
+}
+
+/// Error of S3 handlers, rendered as S3 error document by [`error_layer`]
+#[derive(Debug)]
+pub struct S3ErrorResponse(ErrorReport);
+
+impl<E: Into<ErrorReport>> From<E> for S3ErrorResponse {
+  #[track_caller]
+  fn from(value: E) -> Self {
+    Self(value.into())
+  }
+}
+
+#[derive(Clone, Debug)]
+struct ErrorInfo {
+  code: S3ErrorCode,
+  message: String,
+  region: Option<String>,
+}
+
+impl IntoResponse for S3ErrorResponse {
+  fn into_response(self) -> Response {
+    let report = body_error(self.0);
+    let info = match report.error.downcast_ref::<S3Error>() {
+      Some(error) => ErrorInfo {
+        code: error.code,
+        message: error.message.clone(),
+        region: error.region.clone(),
+      },
+      None if report.status.is_server_error() => ErrorInfo {
+        code: S3ErrorCode::from_status(report.status),
+        message: INTERNAL_ERROR_MESSAGE.into(),
+        region: None,
+      },
+      None => ErrorInfo {
+        code: S3ErrorCode::from_status(report.status),
+        message: report.error.to_string(),
+        region: None,
+      },
+    };
+
+    let mut res = report.into_response();
+    res.extensions_mut().insert(info);
+    res
+  }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename = "Error", rename_all = "PascalCase")]
+struct ErrorDocument {
+  code: String,
+  message: String,
+  resource: String,
+  request_id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  region: Option<String>,
+}
+
+/// Assigns a request id to every response and renders errors as S3 error documents
+#[instrument(skip_all)]
+pub async fn error_layer(req: Request, next: Next) -> Response {
+  let resource = req.uri().path().to_string();
+  let request_id = Uuid::new_v4().simple().to_string()[..16].to_uppercase();
+
+  let mut res = next.run(req).await;
+  let status = res.status();
+  if status.is_client_error() || status.is_server_error() {
+    let info = match res.extensions_mut().remove::<ErrorInfo>() {
+      Some(info) => info,
+      None => {
+        // rejections of extractors and the router only carry a plain text message
+        let body = std::mem::replace(res.body_mut(), Body::empty());
+        let message = to_bytes(body, MAX_MESSAGE_BODY)
+          .await
+          .map(|body| String::from_utf8_lossy(&body).to_string())
+          .unwrap_or_default();
+        ErrorInfo {
+          code: S3ErrorCode::from_status(status),
+          message,
+          region: None,
+        }
+      }
+    };
+
+    let (mut parts, _) = res.into_parts();
+    let document = Xml(ErrorDocument {
+      code: info.code.to_string(),
+      message: info.message,
+      resource,
+      request_id: request_id.clone(),
+      region: info.region,
+    })
+    .into_response();
+    parts.headers.extend(
+      document
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    res = Response::from_parts(parts, document.into_body());
+  }
+
+  if let Ok(value) = HeaderValue::from_str(&request_id) {
+    res.headers_mut().insert(AWZ_REQUEST_ID, value);
+  }
+  res
+}
+
+#[cfg(test)]
+mod test {
+  use axum::{Router, middleware, routing::get};
+  use tower::ServiceExt;
+
+  use super::*;
+
+  async fn no_such_bucket() -> Result<()> {
+    s3_bail!(NoSuchBucket, "Bucket {} not found", "missing");
+  }
+
+  async fn internal() -> Result<()> {
+    Err(std::io::Error::other("disk on fire").into())
+  }
+
+  async fn body() -> Result<()> {
+    let error = BodyError(S3Error::new(S3ErrorCode::BadDigest, "checksum mismatch").into());
+    Err(std::io::Error::from(error).into())
+  }
+
+  async fn call(uri: &str) -> (StatusCode, String) {
+    let router = Router::new()
+      .route("/missing", get(no_such_bucket))
+      .route("/internal", get(internal))
+      .route("/body", get(body))
+      .layer(middleware::from_fn(error_layer));
+
+    let res = router
+      .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+      .await
+      .unwrap();
+    assert!(res.headers().contains_key(AWZ_REQUEST_ID));
+    let status = res.status();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&body).to_string())
+  }
+
+  #[tokio::test]
+  async fn test_s3_error() {
+    let (status, body) = call("/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("<Code>NoSuchBucket</Code>"));
+    assert!(body.contains("<Message>Bucket missing not found</Message>"));
+    assert!(body.contains("<Resource>/missing</Resource>"));
+    assert!(body.contains("<RequestId>"));
+  }
+
+  #[tokio::test]
+  async fn test_internal_error() {
+    let (status, body) = call("/internal").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains("<Code>InternalError</Code>"));
+    // details of internal errors are only logged
+    assert!(!body.contains("disk on fire"));
+  }
+
+  #[tokio::test]
+  async fn test_body_error() {
+    let (status, body) = call("/body").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("<Code>BadDigest</Code>"));
+  }
+
+  #[tokio::test]
+  async fn test_router_error() {
+    let (status, body) = call("/unknown").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // an unknown route does not claim that a key is missing
+    assert!(body.contains("<Code>NotFound</Code>"));
+  }
+
+  #[test]
+  fn test_code_names() {
+    assert_eq!(S3ErrorCode::MalformedXml.to_string(), "MalformedXML");
+    assert_eq!(S3ErrorCode::BucketNotEmpty.to_string(), "BucketNotEmpty");
+    assert_eq!(S3ErrorCode::BucketNotEmpty.status(), StatusCode::CONFLICT);
+  }
+}
@@ -0,0 +1,62 @@
+use std::{
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+use md5::Digest;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Wraps a reader and feeds every byte read through it into a digest
+/// This allows hashing data while it is streamed into the storage
+pub struct HashReader<'r, D> {
+  reader: &'r mut (dyn AsyncRead + Unpin + Send),
+  hasher: D,
+}
+
+impl<'r, D: Digest> HashReader<'r, D> {
+  pub fn new(reader: &'r mut (dyn AsyncRead + Unpin + Send)) -> Self {
+    Self {
+      reader,
+      hasher: D::new(),
+    }
+  }
+
+  pub fn finalize_hex(self) -> String {
+    hex::encode(self.hasher.finalize())
+  }
+}
+
+impl<D: Digest + Unpin> AsyncRead for HashReader<'_, D> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    let this = self.get_mut();
+    let filled = buf.filled().len();
+    let res = Pin::new(&mut *this.reader).poll_read(cx, buf);
+    if let Poll::Ready(Ok(())) = res {
+      this.hasher.update(&buf.filled()[filled..]);
+    }
+    res
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use md5::Md5;
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_hash_reader() {
+    let mut data = &b"Hello, world!"[..];
+    let mut reader = HashReader::<Md5>::new(&mut data);
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).await.unwrap();
+
+    assert_eq!(buf, b"Hello, world!");
+    assert_eq!(reader.finalize_hex(), "6cd3556deb0da54bca060b4c39479839");
+  }
+}
@@ -39,7 +39,7 @@ typed_header!(
 typed_header!(
   AwzDecodedContentLength,
   AWZ_DECODED_CONTENT_LENGTH,
-  "x-awz-decoded-content-length",
+  "x-amz-decoded-content-length",
   u64
 );
 
@@ -63,6 +63,33 @@ typed_header!(
   "x-amz-grant-write-acp"
 );
 
+typed_header!(
+  AwzMpPartsCount,
+  AWZ_MP_PARTS_COUNT,
+  "x-amz-mp-parts-count",
+  usize
+);
+
+typed_header!(AwzCopySource, AWZ_COPY_SOURCE, "x-amz-copy-source");
+typed_header!(
+  AwzCopySourceRange,
+  AWZ_COPY_SOURCE_RANGE,
+  "x-amz-copy-source-range"
+);
+typed_header!(
+  AwzMetadataDirectiveHeader,
+  AWZ_METADATA_DIRECTIVE,
+  "x-amz-metadata-directive",
+  AwzMetadataDirective
+);
+
+#[derive(Deserialize_enum_str, Serialize_enum_str, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub enum AwzMetadataDirective {
+  Copy,
+  Replace,
+}
+
 #[derive(Deserialize_enum_str, Serialize_enum_str, Debug, PartialEq)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum AwzContentSha256 {
@@ -121,6 +148,21 @@ mod test {
     );
   }
 
+  #[test]
+  fn test_awz_metadata_directive() {
+    for (raw, expected) in [
+      ("COPY", AwzMetadataDirective::Copy),
+      ("REPLACE", AwzMetadataDirective::Replace),
+    ] {
+      let value = HeaderValue::from_static(raw);
+      let directive = AwzMetadataDirectiveHeader::decode(&mut std::iter::once(&value)).unwrap();
+      assert_eq!(directive.0, expected);
+    }
+
+    let value = HeaderValue::from_static("MOVE");
+    assert!(AwzMetadataDirectiveHeader::decode(&mut std::iter::once(&value)).is_err());
+  }
+
   #[test]
   fn test_awz_content_sha256() {
     for (raw, expected) in [
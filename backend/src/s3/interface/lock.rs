@@ -0,0 +1,85 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex, Weak},
+};
+
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+type LockMap = HashMap<(String, String), Weak<RwLock<()>>>;
+
+/// Serializes writes to the same key, locks are dropped once nobody holds them
+///
+/// Shared locks only exclude exclusive ones, so operations that may run alongside
+/// each other can still be serialized against one that may not
+#[derive(Debug, Default)]
+pub struct KeyLocks {
+  locks: Mutex<LockMap>,
+}
+
+impl KeyLocks {
+  pub async fn lock(&self, bucket: &str, key: &str) -> OwnedRwLockWriteGuard<()> {
+    self.get(bucket, key).write_owned().await
+  }
+
+  pub async fn lock_shared(&self, bucket: &str, key: &str) -> OwnedRwLockReadGuard<()> {
+    self.get(bucket, key).read_owned().await
+  }
+
+  fn get(&self, bucket: &str, key: &str) -> Arc<RwLock<()>> {
+    let mut locks = self.locks.lock().unwrap_or_else(|e| e.into_inner());
+    locks.retain(|_, lock| lock.strong_count() > 0);
+
+    let entry = locks
+      .entry((bucket.to_string(), key.to_string()))
+      .or_default();
+    match entry.upgrade() {
+      Some(lock) => lock,
+      None => {
+        let lock = Arc::new(RwLock::new(()));
+        *entry = Arc::downgrade(&lock);
+        lock
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_key_locks() {
+    let locks = Arc::new(KeyLocks::default());
+
+    let guard = locks.lock("bucket", "key").await;
+    // other keys are not blocked
+    let other = locks.lock("bucket", "other").await;
+    drop(other);
+
+    let waiting = tokio::spawn({
+      let locks = locks.clone();
+      async move { locks.lock("bucket", "key").await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished());
+
+    drop(guard);
+    drop(waiting.await.unwrap());
+
+    // shared locks only wait for exclusive ones
+    let first = locks.lock_shared("bucket", "key").await;
+    let second = locks.lock_shared("bucket", "key").await;
+    let waiting = tokio::spawn({
+      let locks = locks.clone();
+      async move { locks.lock("bucket", "key").await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished());
+    drop((first, second));
+    drop(waiting.await.unwrap());
+    locks.lock("bucket", "cleanup").await;
+    assert_eq!(locks.locks.lock().unwrap().len(), 1);
+  }
+}
@@ -0,0 +1,1264 @@
+use axum::Extension;
+use centaurus::{FromReqExtension, error::Result, path};
+use chrono::{DateTime, Utc};
+use entity::object;
+use http::StatusCode;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use tokio::{
+  fs::File,
+  io::{AsyncRead, AsyncReadExt},
+  sync::OwnedRwLockWriteGuard,
+};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+  db::Connection,
+  macros::DualRouterExt,
+  router_extension,
+  s3::{
+    BUCKET_DIR,
+    checksum::Checksum,
+    hash::HashReader,
+    interface::lock::KeyLocks,
+    storage::{no_raid::TEMP_PREFIX, walk::KeyWalker},
+  },
+  s3_bail,
+};
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::ErrorKind,
+  net::Ipv4Addr,
+  ops::Deref,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use crate::s3::storage::Storage;
+
+mod lock;
+mod multipart;
+mod scrub;
+
+pub use scrub::ScrubMetrics;
+
+const MAX_KEY_LENGTH: usize = 1024;
+const MIN_BUCKET_LENGTH: usize = 3;
+const MAX_BUCKET_LENGTH: usize = 63;
+
+/// Content of an object that is written to the storage
+pub trait ObjectReader: AsyncRead + Unpin + Send {
+  /// Checksum that was verified while the content was read, only known once it was read to the end
+  fn checksum(&self) -> Option<Checksum> {
+    None
+  }
+}
+
+impl ObjectReader for &[u8] {}
+
+impl ObjectReader for File {}
+
+impl ObjectReader for Box<dyn AsyncRead + Unpin + Send> {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+  pub size: u64,
+  pub last_modified: DateTime<Utc>,
+  pub etag: String,
+  pub metadata: ObjectMetadata,
+  /// sizes of the parts of objects created by multipart uploads, empty otherwise
+  pub parts: Vec<u64>,
+}
+
+/// Attributes provided by the client when the object was written
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ObjectMetadata {
+  pub content_type: Option<String>,
+  pub cache_control: Option<String>,
+  pub content_disposition: Option<String>,
+  /// `x-amz-meta-*` headers without the prefix
+  pub user: BTreeMap<String, String>,
+  /// flexible checksum of the content, verified when it was received
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub checksum: Option<Checksum>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketInfo {
+  pub name: String,
+  pub created: DateTime<Utc>,
+  /// user that created the bucket, `None` for anonymous requests
+  pub owner: Option<Uuid>,
+  pub region: String,
+  pub settings: BucketSettings,
+}
+
+/// Options chosen when the bucket was created
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct BucketSettings {
+  #[serde(default)]
+  pub object_lock_enabled: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ListOptions {
+  pub prefix: String,
+  pub delimiter: Option<String>,
+  /// only keys after this one are listed, if it is a common prefix all keys in it are skipped
+  pub start_after: Option<String>,
+  pub max_keys: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ListPage {
+  pub objects: Vec<ListedObject>,
+  pub common_prefixes: Vec<String>,
+  pub is_truncated: bool,
+  /// last key or common prefix in this page, used to continue the listing
+  pub last_key: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ListedObject {
+  pub key: String,
+  pub info: ObjectInfo,
+}
+
+/// Precondition of a write that is checked while no other write to the key runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteCondition {
+  /// `If-None-Match: *`, the object must not exist yet
+  NotExists,
+  /// `If-Match`, the object must exist with this etag, `*` matches every etag
+  Matches(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopySource {
+  pub bucket: String,
+  pub key: String,
+}
+
+#[derive(Clone, FromReqExtension, Debug)]
+pub struct S3Interface {
+  storage: Arc<dyn Storage + Send + Sync>,
+  db: Connection,
+  locks: Arc<KeyLocks>,
+  /// locks of multipart uploads keyed by upload id and part
+  upload_locks: Arc<KeyLocks>,
+}
+
+impl S3Interface {
+  pub fn new<S: Storage + Send + Sync + 'static>(storage: S, db: Connection) -> Self {
+    Self {
+      storage: Arc::new(storage),
+      db,
+      locks: Arc::new(KeyLocks::default()),
+      upload_locks: Arc::new(KeyLocks::default()),
+    }
+  }
+
+  #[instrument]
+  pub async fn create_bucket(
+    &self,
+    bucket: &String,
+    owner: Option<Uuid>,
+    region: &str,
+    settings: BucketSettings,
+  ) -> Result<BucketInfo> {
+    validate_bucket_name(bucket)?;
+    self.check_new_bucket(bucket, owner).await?;
+
+    let model = entity::bucket::Model {
+      id: Uuid::new_v4(),
+      name: bucket.clone(),
+      owner,
+      region: region.into(),
+      settings: serde_json::to_value(&settings)?,
+      created: Utc::now().naive_utc(),
+    };
+    match self.db.bucket().create_bucket(model.clone()).await {
+      Ok(()) => (),
+      // another request created the bucket since it was checked
+      Err(e) if e.status == StatusCode::CONFLICT => {
+        self.check_new_bucket(bucket, owner).await?;
+        s3_bail!(BucketAlreadyExists, "Bucket {bucket} already exists");
+      }
+      Err(e) => return Err(e),
+    }
+
+    if let Err(e) = self.create_dir(&path!(BUCKET_DIR, &bucket)).await {
+      self.db.bucket().delete_bucket(bucket).await?;
+      return Err(e.into());
+    }
+
+    bucket_info(model)
+  }
+
+  /// Rejects creating a bucket with the name of an existing one
+  async fn check_new_bucket(&self, bucket: &String, owner: Option<Uuid>) -> Result<()> {
+    if let Some(existing) = self.db.bucket().get_bucket(bucket).await? {
+      if owner.is_some() && existing.owner == owner {
+        s3_bail!(BucketAlreadyOwnedByYou, "Bucket {bucket} already exists");
+      }
+      s3_bail!(BucketAlreadyExists, "Bucket {bucket} already exists");
+    }
+    Ok(())
+  }
+
+  #[instrument]
+  pub async fn delete_bucket(&self, bucket: &String) -> Result<()> {
+    self.bucket(bucket).await?;
+
+    // a concurrent delete may remove the bucket after it was found
+    let objects = match self.list_dir(&path!(BUCKET_DIR, &bucket)).await {
+      Ok(objects) => objects,
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        s3_bail!(NoSuchBucket, "Bucket {bucket} not found")
+      }
+      Err(e) => return Err(e.into()),
+    };
+    if !objects.is_empty() {
+      s3_bail!(BucketNotEmpty, "Bucket {bucket} is not empty");
+    }
+
+    match self.delete_dir(&path!(BUCKET_DIR, &bucket)).await {
+      Ok(()) => (),
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        s3_bail!(NoSuchBucket, "Bucket {bucket} not found")
+      }
+      Err(e) => return Err(e.into()),
+    }
+    match self.db.bucket().delete_bucket(bucket).await {
+      Ok(()) => Ok(()),
+      Err(e) if e.status == StatusCode::NOT_FOUND => {
+        s3_bail!(NoSuchBucket, "Bucket {bucket} not found")
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Lists all buckets sorted by name
+  #[instrument]
+  pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>> {
+    self
+      .db
+      .bucket()
+      .list_buckets()
+      .await?
+      .into_iter()
+      .map(bucket_info)
+      .collect()
+  }
+
+  #[instrument]
+  pub async fn bucket(&self, bucket: &String) -> Result<BucketInfo> {
+    let Some(model) = self.db.bucket().get_bucket(bucket).await? else {
+      s3_bail!(NoSuchBucket, "Bucket {bucket} not found");
+    };
+
+    bucket_info(model)
+  }
+
+  #[instrument(skip(reader))]
+  pub async fn put_object(
+    &self,
+    bucket: &String,
+    key: &str,
+    metadata: ObjectMetadata,
+    reader: &mut dyn ObjectReader,
+    condition: Option<&WriteCondition>,
+  ) -> Result<ObjectInfo> {
+    let path = self.object_path(bucket, key).await?;
+    let _lock = self.locks.lock(bucket, key).await;
+    self.check_write(bucket, key, condition).await?;
+
+    if let Some(parent) = path.parent() {
+      key_conflict(self.create_dir(parent).await, key)?;
+    }
+
+    let mut hash_reader = HashReader::<Md5>::new(reader);
+    key_conflict(self.stream_write_file(&path, &mut hash_reader).await, key)?;
+    let etag = hash_reader.finalize_hex();
+    let size = self.file_info(&path).await?.size;
+    let metadata = ObjectMetadata {
+      checksum: metadata.checksum.or_else(|| reader.checksum()),
+      ..metadata
+    };
+
+    self
+      .save_object(bucket, key, size, etag, metadata, Vec::new())
+      .await
+  }
+
+  #[instrument]
+  pub async fn head_object(&self, bucket: &String, key: &str) -> Result<ObjectInfo> {
+    let path = self.object_path(bucket, key).await?;
+    let info = match self.file_info(&path).await {
+      Ok(info) => info,
+      Err(e) if is_missing(&e) => s3_bail!(NoSuchKey, "Object {key} not found"),
+      Err(e) => return Err(e.into()),
+    };
+
+    if let Some(object) = self.db.object().get_object(bucket, key).await? {
+      return Ok(ObjectInfo {
+        size: info.size,
+        ..object_info(object)?
+      });
+    }
+
+    // objects written before the metadata store existed have no record, so the etag
+    // is computed from the content once and recorded for later requests
+    let mut reader = self.stream_read_file(&path).await?;
+    let mut reader = HashReader::<Md5>::new(&mut reader);
+    let mut buf = vec![0; 64 * 1024];
+    while reader.read(&mut buf).await? > 0 {}
+    let etag = reader.finalize_hex();
+
+    // a write that recorded the object in the meantime is kept
+    self
+      .db
+      .object()
+      .insert_object(object::Model {
+        id: Uuid::new_v4(),
+        bucket: bucket.clone(),
+        key: key.to_string(),
+        size: info.size as i64,
+        etag: etag.clone(),
+        content_type: None,
+        cache_control: None,
+        content_disposition: None,
+        metadata: serde_json::to_value(BTreeMap::<String, String>::new())?,
+        created: info.modified.naive_utc(),
+        parts: None,
+        checksum: None,
+      })
+      .await?;
+
+    Ok(ObjectInfo {
+      size: info.size,
+      last_modified: info.modified,
+      etag,
+      metadata: ObjectMetadata::default(),
+      parts: Vec::new(),
+    })
+  }
+
+  /// Blocks writes to the key, the metadata and the reader of an object opened while
+  /// holding it belong to the same write
+  pub async fn lock_object(&self, bucket: &str, key: &str) -> OwnedRwLockWriteGuard<()> {
+    self.locks.lock(bucket, key).await
+  }
+
+  #[instrument]
+  pub async fn get_object(
+    &self,
+    bucket: &String,
+    key: &str,
+  ) -> Result<(ObjectInfo, Box<dyn AsyncRead + Unpin + Send>)> {
+    let _lock = self.lock_object(bucket, key).await;
+    let info = self.head_object(bucket, key).await?;
+    let reader = self.read_object(bucket, key, None).await?;
+
+    Ok((info, reader))
+  }
+
+  /// Reads the object or only the bytes from `first` to `last` inclusive
+  #[instrument]
+  pub async fn read_object(
+    &self,
+    bucket: &String,
+    key: &str,
+    range: Option<(u64, u64)>,
+  ) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let path = self.object_path(bucket, key).await?;
+    let reader = match range {
+      Some((first, last)) => {
+        self
+          .stream_read_file_range(&path, first, last - first + 1)
+          .await
+      }
+      None => self.stream_read_file(&path).await,
+    };
+
+    match reader {
+      Ok(reader) => Ok(reader),
+      Err(e) if is_missing(&e) => s3_bail!(NoSuchKey, "Object {key} not found"),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  #[instrument]
+  pub async fn delete_object(&self, bucket: &String, key: &str) -> Result<()> {
+    let path = self.object_path(bucket, key).await?;
+    let _lock = self.locks.lock(bucket, key).await;
+    match self.delete_file(&path).await {
+      Ok(()) => (),
+      // deleting a non existing object is not an error in s3
+      Err(e) if is_missing(&e) => return Ok(()),
+      Err(e) => return Err(e.into()),
+    }
+    self.db.object().delete_object(bucket, key).await?;
+
+    // remove directories that only existed for this object, other keys in them are
+    // not locked so a directory is only removed while it is empty
+    let bucket_path = path!(BUCKET_DIR, bucket);
+    let mut dir = path.parent();
+    while let Some(current) = dir
+      && current != bucket_path
+    {
+      match self.delete_empty_dir(current).await {
+        Ok(()) => {}
+        Err(e) if matches!(e.kind(), ErrorKind::DirectoryNotEmpty | ErrorKind::NotFound) => break,
+        Err(e) => return Err(e.into()),
+      }
+      dir = current.parent();
+    }
+
+    Ok(())
+  }
+
+  /// Copies an object server side, the data is streamed directly between the files
+  ///
+  /// Without new `metadata` the metadata of the source object is copied
+  #[instrument]
+  pub async fn copy_object(
+    &self,
+    source: &CopySource,
+    bucket: &String,
+    key: &str,
+    metadata: Option<ObjectMetadata>,
+  ) -> Result<ObjectInfo> {
+    let source_info = self.head_object(&source.bucket, &source.key).await?;
+    let metadata = match metadata {
+      // the content is unchanged, so its checksum stays valid
+      Some(metadata) => ObjectMetadata {
+        checksum: source_info.metadata.checksum,
+        ..metadata
+      },
+      None => source_info.metadata,
+    };
+
+    let source_path = self.object_path(&source.bucket, &source.key).await?;
+    if source_path == self.object_path(bucket, key).await? {
+      // copying an object onto itself only replaces its metadata
+      let _lock = self.locks.lock(bucket, key).await;
+      return self
+        .save_object(
+          bucket,
+          key,
+          source_info.size,
+          source_info.etag,
+          metadata,
+          source_info.parts,
+        )
+        .await;
+    }
+
+    let mut reader = self.open_object(&source_path, &source.key).await?;
+    self
+      .put_object(bucket, key, metadata, &mut reader, None)
+      .await
+  }
+
+  #[instrument]
+  pub async fn list_objects(&self, bucket: &String, options: &ListOptions) -> Result<ListPage> {
+    self.bucket(bucket).await?;
+
+    let delimiter = options.delimiter.as_deref().filter(|d| !d.is_empty());
+    let mut walker = KeyWalker::new(
+      &*self.storage,
+      path!(BUCKET_DIR, bucket),
+      &options.prefix,
+      options.start_after.as_deref(),
+    );
+    let mut page = ListPage::default();
+    let mut keys = Vec::new();
+    let mut count = 0;
+
+    while let Some(key) = walker.next().await? {
+      let common_prefix = delimiter.and_then(|delimiter| {
+        key[options.prefix.len()..]
+          .find(delimiter)
+          .map(|i| key[..options.prefix.len() + i + delimiter.len()].to_string())
+      });
+
+      if let Some(common_prefix) = common_prefix {
+        walker.skip_prefix(&common_prefix);
+        if options.start_after.as_ref() == Some(&common_prefix) {
+          continue;
+        }
+        if count == options.max_keys {
+          page.is_truncated = true;
+          break;
+        }
+
+        page.last_key = Some(common_prefix.clone());
+        page.common_prefixes.push(common_prefix);
+      } else {
+        if count == options.max_keys {
+          page.is_truncated = true;
+          break;
+        }
+
+        page.last_key = Some(key.clone());
+        keys.push(key);
+      }
+      count += 1;
+    }
+
+    // the metadata of the whole page is loaded at once
+    let mut objects: HashMap<_, _> = self
+      .db
+      .object()
+      .get_objects(bucket, &keys)
+      .await?
+      .into_iter()
+      .map(|object| (object.key.clone(), object))
+      .collect();
+    for key in keys {
+      let info = match objects.remove(&key) {
+        Some(object) => object_info(object)?,
+        None => match self.head_object(bucket, &key).await {
+          Ok(info) => info,
+          // the object was deleted since it was listed
+          Err(e) if e.status == StatusCode::NOT_FOUND => continue,
+          Err(e) => return Err(e),
+        },
+      };
+      page.objects.push(ListedObject { key, info });
+    }
+
+    Ok(page)
+  }
+
+  #[instrument]
+  async fn save_object(
+    &self,
+    bucket: &str,
+    key: &str,
+    size: u64,
+    etag: String,
+    metadata: ObjectMetadata,
+    parts: Vec<u64>,
+  ) -> Result<ObjectInfo> {
+    let last_modified = Utc::now();
+    self
+      .db
+      .object()
+      .save_object(object::Model {
+        id: Uuid::new_v4(),
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        size: size as i64,
+        etag: etag.clone(),
+        content_type: metadata.content_type.clone(),
+        cache_control: metadata.cache_control.clone(),
+        content_disposition: metadata.content_disposition.clone(),
+        metadata: serde_json::to_value(&metadata.user)?,
+        created: last_modified.naive_utc(),
+        parts: if parts.is_empty() {
+          None
+        } else {
+          Some(serde_json::to_value(&parts)?)
+        },
+        checksum: metadata
+          .checksum
+          .as_ref()
+          .map(serde_json::to_value)
+          .transpose()?,
+      })
+      .await?;
+
+    Ok(ObjectInfo {
+      size,
+      last_modified,
+      etag,
+      metadata,
+      parts,
+    })
+  }
+
+  /// Rejects the write if the current state of the key does not satisfy the condition
+  #[instrument]
+  async fn check_write(
+    &self,
+    bucket: &String,
+    key: &str,
+    condition: Option<&WriteCondition>,
+  ) -> Result<()> {
+    let Some(condition) = condition else {
+      return Ok(());
+    };
+
+    let current = match self.head_object(bucket, key).await {
+      Ok(info) => Some(info),
+      Err(e) if e.status == StatusCode::NOT_FOUND => None,
+      Err(e) => return Err(e),
+    };
+
+    match (condition, current) {
+      (WriteCondition::NotExists, None) => Ok(()),
+      (WriteCondition::NotExists, Some(_)) => s3_bail!(
+        PreconditionFailed,
+        "At least one of the pre-conditions you specified did not hold"
+      ),
+      (WriteCondition::Matches(_), None) => s3_bail!(NoSuchKey, "Object {key} not found"),
+      (WriteCondition::Matches(etag), Some(info)) => {
+        if etag != "*" && etag.trim_matches('"') != info.etag {
+          s3_bail!(
+            PreconditionFailed,
+            "At least one of the pre-conditions you specified did not hold"
+          );
+        }
+        Ok(())
+      }
+    }
+  }
+
+  #[instrument]
+  async fn open_object(&self, path: &Path, key: &str) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    match self.stream_read_file(path).await {
+      Ok(reader) => Ok(reader),
+      Err(e) if is_missing(&e) => s3_bail!(NoSuchKey, "Object {key} not found"),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  #[instrument]
+  async fn object_path(&self, bucket: &String, key: &str) -> Result<PathBuf> {
+    validate_bucket_name(bucket)?;
+    self.bucket(bucket).await?;
+
+    if key.is_empty() {
+      s3_bail!(InvalidArgument, "Object key must not be empty");
+    }
+    if key.len() > MAX_KEY_LENGTH {
+      s3_bail!(
+        KeyTooLongError,
+        "Object key must be at most {MAX_KEY_LENGTH} bytes long"
+      );
+    }
+    // objects are stored as files and can not also be the directory of the keys below
+    // them, so folder markers are not supported
+    if key.ends_with('/') {
+      s3_bail!(
+        InvalidArgument,
+        "Object keys ending with / are not supported"
+      );
+    }
+    // segments with the temp prefix would be hidden from listings and removed on startup
+    if key.split('/').any(|segment| {
+      segment.is_empty() || segment == "." || segment == ".." || segment.starts_with(TEMP_PREFIX)
+    }) {
+      s3_bail!(InvalidArgument, "Object key {key} is not supported");
+    }
+
+    Ok(Path::new(BUCKET_DIR).join(bucket).join(key))
+  }
+}
+
+impl Deref for S3Interface {
+  type Target = dyn Storage + Send + Sync;
+
+  fn deref(&self) -> &Self::Target {
+    &*self.storage
+  }
+}
+
+/// Bucket names are used as directory names, so only names valid in S3 are accepted
+fn validate_bucket_name(bucket: &str) -> Result<()> {
+  let valid = (MIN_BUCKET_LENGTH..=MAX_BUCKET_LENGTH).contains(&bucket.len())
+    && bucket
+      .bytes()
+      .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+    && bucket.starts_with(|c: char| c.is_ascii_alphanumeric())
+    && bucket.ends_with(|c: char| c.is_ascii_alphanumeric())
+    && !bucket.contains("..")
+    && bucket.parse::<Ipv4Addr>().is_err();
+
+  if !valid {
+    s3_bail!(
+      InvalidBucketName,
+      "The specified bucket {bucket} is not valid"
+    );
+  }
+  Ok(())
+}
+
+/// Whether an object is missing, keys below an object have a file where their directory
+/// would be and keys that are only a prefix of other keys are a directory
+pub(super) fn is_missing(e: &std::io::Error) -> bool {
+  matches!(
+    e.kind(),
+    ErrorKind::NotFound | ErrorKind::NotADirectory | ErrorKind::IsADirectory
+  )
+}
+
+/// Rejects writes of keys that would need an object to be a directory or a directory
+/// of other keys to be an object
+pub(super) fn key_conflict(result: std::io::Result<()>, key: &str) -> Result<()> {
+  match result {
+    Err(e)
+      if matches!(
+        e.kind(),
+        ErrorKind::AlreadyExists | ErrorKind::NotADirectory | ErrorKind::IsADirectory
+      ) =>
+    {
+      s3_bail!(
+        InvalidRequest,
+        "Object key {key} conflicts with an existing object or prefix"
+      )
+    }
+    result => Ok(result?),
+  }
+}
+
+fn object_info(object: object::Model) -> Result<ObjectInfo> {
+  Ok(ObjectInfo {
+    size: object.size as u64,
+    last_modified: object.created.and_utc(),
+    etag: object.etag,
+    metadata: ObjectMetadata {
+      content_type: object.content_type,
+      cache_control: object.cache_control,
+      content_disposition: object.content_disposition,
+      user: serde_json::from_value(object.metadata)?,
+      checksum: object.checksum.map(serde_json::from_value).transpose()?,
+    },
+    parts: object
+      .parts
+      .map(serde_json::from_value)
+      .transpose()?
+      .unwrap_or_default(),
+  })
+}
+
+fn bucket_info(model: entity::bucket::Model) -> Result<BucketInfo> {
+  Ok(BucketInfo {
+    name: model.name,
+    created: model.created.and_utc(),
+    owner: model.owner,
+    region: model.region,
+    settings: serde_json::from_value(model.settings)?,
+  })
+}
+
+router_extension!(
+  async fn interface(self, interface: S3Interface) -> Self {
+    self.layer(Extension(interface))
+  }
+);
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    config::EnvConfig,
+    db::test::test_db,
+    s3::{checksum::ChecksumAlgorithm, storage::StorageType},
+  };
+
+  use super::*;
+
+  const REGION: &str = "us-east-1";
+
+  pub(super) async fn interface() -> (S3Interface, PathBuf) {
+    let temp_dir = std::env::temp_dir().join(format!("sagittarius-{}", uuid::Uuid::new_v4()));
+    let config = EnvConfig {
+      storage_path: temp_dir.clone(),
+      ..Default::default()
+    };
+    let interface = StorageType::NoRaid
+      .storage(&config, test_db().await)
+      .await
+      .unwrap();
+    interface
+      .create_bucket(&"bucket".into(), None, REGION, BucketSettings::default())
+      .await
+      .unwrap();
+
+    (interface, temp_dir)
+  }
+
+  #[tokio::test]
+  async fn test_object_lifecycle() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let metadata = ObjectMetadata {
+      content_type: Some("text/plain".into()),
+      user: BTreeMap::from([("color".into(), "blue".into())]),
+      checksum: Some(Checksum {
+        algorithm: ChecksumAlgorithm::Crc32,
+        value: "6+bG5g==".into(),
+      }),
+      ..Default::default()
+    };
+    let etag = interface
+      .put_object(
+        &bucket,
+        "dir/hello.txt",
+        metadata.clone(),
+        &mut &b"Hello, world!"[..],
+        None,
+      )
+      .await?
+      .etag;
+    assert_eq!(etag, "6cd3556deb0da54bca060b4c39479839");
+
+    let (info, mut reader) = interface.get_object(&bucket, "dir/hello.txt").await?;
+    assert_eq!(info.size, 13);
+    assert_eq!(info.etag, etag);
+    assert_eq!(info.metadata, metadata);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    assert_eq!(data, b"Hello, world!");
+
+    interface.delete_object(&bucket, "dir/hello.txt").await?;
+    assert!(
+      interface
+        .head_object(&bucket, "dir/hello.txt")
+        .await
+        .is_err()
+    );
+    // the now empty directory is removed so the bucket can be deleted
+    interface.delete_bucket(&bucket).await?;
+    assert!(interface.list_buckets().await?.is_empty());
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_conditional_put() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+    let not_exists = Some(&WriteCondition::NotExists);
+
+    let etag = interface
+      .put_object(
+        &bucket,
+        "key",
+        ObjectMetadata::default(),
+        &mut &b"first"[..],
+        not_exists,
+      )
+      .await?
+      .etag;
+    let err = interface
+      .put_object(
+        &bucket,
+        "key",
+        ObjectMetadata::default(),
+        &mut &b"second"[..],
+        not_exists,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(err.status, StatusCode::PRECONDITION_FAILED);
+
+    let stale = WriteCondition::Matches("\"stale\"".into());
+    let err = interface
+      .put_object(
+        &bucket,
+        "key",
+        ObjectMetadata::default(),
+        &mut &b"second"[..],
+        Some(&stale),
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(err.status, StatusCode::PRECONDITION_FAILED);
+
+    let current = WriteCondition::Matches(format!("\"{etag}\""));
+    interface
+      .put_object(
+        &bucket,
+        "key",
+        ObjectMetadata::default(),
+        &mut &b"second"[..],
+        Some(&current),
+      )
+      .await?;
+    assert_eq!(interface.head_object(&bucket, "key").await?.size, 6);
+
+    let err = interface
+      .put_object(
+        &bucket,
+        "missing",
+        ObjectMetadata::default(),
+        &mut &b"data"[..],
+        Some(&WriteCondition::Matches("*".into())),
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+    // only one of two concurrent writers may create the object
+    let (mut first, mut second) = (&b"a"[..], &b"b"[..]);
+    let (a, b) = tokio::join!(
+      interface.put_object(
+        &bucket,
+        "race",
+        ObjectMetadata::default(),
+        &mut first,
+        not_exists,
+      ),
+      interface.put_object(
+        &bucket,
+        "race",
+        ObjectMetadata::default(),
+        &mut second,
+        not_exists,
+      ),
+    );
+    assert!(a.is_ok() != b.is_ok());
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_bucket_lifecycle() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+
+    let settings = BucketSettings {
+      object_lock_enabled: true,
+    };
+    let created = interface
+      .create_bucket(&"alpha".into(), None, REGION, settings.clone())
+      .await?;
+    assert!(
+      interface
+        .create_bucket(&"alpha".into(), None, REGION, settings.clone())
+        .await
+        .is_err()
+    );
+
+    // only one of two concurrent creates succeeds, the other one is a conflict
+    let race = "race".to_string();
+    let (a, b) = tokio::join!(
+      interface.create_bucket(&race, None, REGION, BucketSettings::default()),
+      interface.create_bucket(&race, None, REGION, BucketSettings::default()),
+    );
+    let err = match (a, b) {
+      (Ok(_), Err(err)) | (Err(err), Ok(_)) => err,
+      _ => panic!("exactly one create must succeed"),
+    };
+    assert_eq!(err.status, StatusCode::CONFLICT);
+    interface.delete_bucket(&race).await?;
+
+    let buckets = interface.list_buckets().await?;
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0], created);
+    assert_eq!(buckets[0].settings, settings);
+    assert_eq!(buckets[1].name, "bucket");
+
+    interface.delete_bucket(&"alpha".into()).await?;
+    assert!(interface.bucket(&"alpha".into()).await.is_err());
+    assert!(interface.delete_bucket(&"alpha".into()).await.is_err());
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_copy_object() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let metadata = ObjectMetadata {
+      content_type: Some("text/plain".into()),
+      ..Default::default()
+    };
+    let etag = interface
+      .put_object(
+        &bucket,
+        "source",
+        metadata.clone(),
+        &mut &b"Hello, world!"[..],
+        None,
+      )
+      .await?
+      .etag;
+    let source = CopySource {
+      bucket: bucket.clone(),
+      key: "source".into(),
+    };
+    let info = interface
+      .copy_object(&source, &bucket, "dir/copy", None)
+      .await?;
+    assert_eq!(info.etag, etag);
+    assert_eq!(info.size, 13);
+    assert_eq!(info.metadata, metadata);
+
+    let (_, mut reader) = interface.get_object(&bucket, "dir/copy").await?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    assert_eq!(data, b"Hello, world!");
+
+    // copying onto itself must not truncate the object
+    let info = interface
+      .copy_object(&source, &bucket, "source", Some(ObjectMetadata::default()))
+      .await?;
+    assert_eq!(info.size, 13);
+    let info = interface.head_object(&bucket, "source").await?;
+    assert_eq!(info.metadata, ObjectMetadata::default());
+
+    assert!(
+      interface
+        .copy_object(
+          &CopySource {
+            bucket: bucket.clone(),
+            key: "missing".into(),
+          },
+          &bucket,
+          "copy",
+          None
+        )
+        .await
+        .is_err()
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_list_objects() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    for key in ["a.txt", "a/b", "a/c/d", "a0", "b/e"] {
+      interface
+        .put_object(
+          &bucket,
+          key,
+          ObjectMetadata::default(),
+          &mut &b"data"[..],
+          None,
+        )
+        .await?;
+    }
+
+    let page = interface
+      .list_objects(
+        &bucket,
+        &ListOptions {
+          delimiter: Some("/".into()),
+          max_keys: 2,
+          ..Default::default()
+        },
+      )
+      .await?;
+    assert_eq!(page.objects.len(), 1);
+    assert_eq!(page.objects[0].key, "a.txt");
+    assert_eq!(page.common_prefixes, vec!["a/"]);
+    assert!(page.is_truncated);
+    assert_eq!(page.last_key.as_deref(), Some("a/"));
+
+    let page = interface
+      .list_objects(
+        &bucket,
+        &ListOptions {
+          delimiter: Some("/".into()),
+          start_after: page.last_key,
+          max_keys: 2,
+          ..Default::default()
+        },
+      )
+      .await?;
+    assert_eq!(page.objects.len(), 1);
+    assert_eq!(page.objects[0].key, "a0");
+    assert_eq!(page.common_prefixes, vec!["b/"]);
+    assert!(!page.is_truncated);
+
+    let page = interface
+      .list_objects(
+        &bucket,
+        &ListOptions {
+          prefix: "a/".into(),
+          max_keys: 1000,
+          ..Default::default()
+        },
+      )
+      .await?;
+    let keys: Vec<_> = page.objects.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(keys, vec!["a/b", "a/c/d"]);
+    assert!(page.common_prefixes.is_empty());
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_object_without_record() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    interface
+      .write_file(&path!(BUCKET_DIR, &bucket, "legacy"), b"Hello, world!")
+      .await?;
+    let page = interface
+      .list_objects(
+        &bucket,
+        &ListOptions {
+          max_keys: 1000,
+          ..Default::default()
+        },
+      )
+      .await?;
+    assert_eq!(
+      page.objects[0].info.etag,
+      "6cd3556deb0da54bca060b4c39479839"
+    );
+
+    // the etag is recorded so the content is only hashed once
+    let object = interface.db.object().get_object(&bucket, "legacy").await?;
+    assert_eq!(
+      object.map(|object| object.etag).as_deref(),
+      Some("6cd3556deb0da54bca060b4c39479839")
+    );
+    assert_eq!(
+      interface.head_object(&bucket, "legacy").await?,
+      page.objects[0].info
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_object_key_conflict() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    // an object can not be the directory of another key and the other way round
+    for (first, second) in [("a", "a/b"), ("c/d", "c")] {
+      interface
+        .put_object(
+          &bucket,
+          first,
+          ObjectMetadata::default(),
+          &mut &b"data"[..],
+          None,
+        )
+        .await?;
+      let err = interface
+        .put_object(
+          &bucket,
+          second,
+          ObjectMetadata::default(),
+          &mut &b"data"[..],
+          None,
+        )
+        .await
+        .unwrap_err();
+      assert_eq!(err.status, StatusCode::BAD_REQUEST);
+      let err = interface.head_object(&bucket, second).await.unwrap_err();
+      assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+    let Err(err) = interface.get_object(&bucket, "a/b").await else {
+      panic!("object below another object exists");
+    };
+    assert_eq!(err.status, StatusCode::NOT_FOUND);
+    interface.delete_object(&bucket, "a/b").await?;
+    interface.delete_object(&bucket, "c").await?;
+    assert_eq!(interface.head_object(&bucket, "c/d").await?.size, 4);
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_object_invalid_key() {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    for key in [
+      "",
+      "../escape",
+      "a//b",
+      "/abs",
+      "dir/",
+      "photos/",
+      ".sagittarius-tmp-key",
+      "dir/.sagittarius-tmp-key",
+    ] {
+      assert!(
+        interface
+          .put_object(&bucket, key, ObjectMetadata::default(), &mut &b""[..], None)
+          .await
+          .is_err()
+      );
+    }
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_invalid_bucket_name() {
+    let (interface, temp_dir) = interface().await;
+
+    for bucket in [
+      "..",
+      "x/../..",
+      "ab",
+      "Upper",
+      "under_score",
+      "-start",
+      "end.",
+      "a..b",
+      "192.168.0.1",
+    ] {
+      let err = interface
+        .create_bucket(&bucket.into(), None, REGION, BucketSettings::default())
+        .await
+        .unwrap_err();
+      assert_eq!(err.status, StatusCode::BAD_REQUEST);
+      let err = interface
+        .put_object(
+          &bucket.into(),
+          "key",
+          ObjectMetadata::default(),
+          &mut &b""[..],
+          None,
+        )
+        .await
+        .unwrap_err();
+      assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+    interface
+      .create_bucket(
+        &"my.bucket-1".into(),
+        None,
+        REGION,
+        BucketSettings::default(),
+      )
+      .await
+      .unwrap();
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_object_missing_bucket() {
+    let (interface, temp_dir) = interface().await;
+
+    assert!(
+      interface
+        .put_object(
+          &"missing".into(),
+          "key",
+          ObjectMetadata::default(),
+          &mut &b""[..],
+          None
+        )
+        .await
+        .is_err()
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+}
@@ -0,0 +1,908 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  io::{self, ErrorKind},
+  path::{Path, PathBuf},
+  pin::Pin,
+  sync::Arc,
+  task::{Context, Poll, ready},
+  time::Duration,
+};
+
+use centaurus::{
+  error::{ErrorReportStatusExt, Result},
+  path,
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use http::StatusCode;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use tokio::{
+  io::{AsyncRead, ReadBuf},
+  sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard},
+  task::JoinHandle,
+};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+  s3::{
+    MULTIPART_DIR,
+    hash::HashReader,
+    interface::{
+      CopySource, ListOptions, ObjectInfo, ObjectMetadata, S3Interface, WriteCondition, is_missing,
+      key_conflict,
+    },
+    storage::Storage,
+  },
+  s3_bail,
+};
+
+const UPLOAD_INFO: &str = "upload.json";
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PART_NUMBER: u16 = 10000;
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UploadInfo {
+  pub bucket: String,
+  pub key: String,
+  pub initiated: DateTime<Utc>,
+  /// metadata the object is created with once the upload is completed
+  #[serde(default)]
+  pub metadata: ObjectMetadata,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PartMeta {
+  etag: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Upload {
+  pub upload_id: String,
+  pub info: UploadInfo,
+}
+
+#[derive(Debug, Default)]
+pub struct UploadPage {
+  pub uploads: Vec<Upload>,
+  pub common_prefixes: Vec<String>,
+  pub is_truncated: bool,
+  /// key or common prefix and upload id of the last entry in this page
+  pub next_key_marker: Option<String>,
+  pub next_upload_id_marker: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PartInfo {
+  pub part_number: u16,
+  pub etag: String,
+  pub size: u64,
+  pub last_modified: DateTime<Utc>,
+}
+
+/// Parts of an upload are staged in `MULTIPART_DIR/<upload id>/` next to a json file
+/// describing the upload, every part is stored as `<part number>` with its etag in `<part number>.json`
+impl S3Interface {
+  #[instrument]
+  pub async fn create_multipart_upload(
+    &self,
+    bucket: &String,
+    key: &str,
+    metadata: ObjectMetadata,
+  ) -> Result<String> {
+    // validates bucket and key before anything is staged
+    self.object_path(bucket, key).await?;
+
+    let upload_id = Uuid::new_v4().to_string();
+    let dir = path!(MULTIPART_DIR, &upload_id);
+    self.create_dir(&dir).await?;
+
+    let info = UploadInfo {
+      bucket: bucket.clone(),
+      key: key.to_string(),
+      initiated: Utc::now(),
+      metadata,
+    };
+    self
+      .write_file(&dir.join(UPLOAD_INFO), &serde_json::to_vec(&info)?)
+      .await?;
+
+    Ok(upload_id)
+  }
+
+  #[instrument(skip(reader))]
+  pub async fn upload_part(
+    &self,
+    bucket: &String,
+    key: &str,
+    upload_id: &str,
+    part_number: u16,
+    reader: &mut (dyn AsyncRead + Unpin + Send),
+  ) -> Result<String> {
+    if !(1..=MAX_PART_NUMBER).contains(&part_number) {
+      s3_bail!(
+        InvalidArgument,
+        "Part number must be between 1 and {MAX_PART_NUMBER}"
+      );
+    }
+    // the upload may not be completed or aborted while the part is written
+    let _upload = self.lock_upload_shared(upload_id).await;
+    let dir = self.upload_dir(bucket, key, upload_id).await?;
+    let _lock = self.lock_part(upload_id, &part_number.to_string()).await;
+
+    let mut reader = HashReader::<Md5>::new(reader);
+    self
+      .stream_write_file(&dir.join(part_number.to_string()), &mut reader)
+      .await?;
+    let etag = reader.finalize_hex();
+
+    let meta = PartMeta { etag: etag.clone() };
+    self
+      .write_file(
+        &dir.join(format!("{part_number}.json")),
+        &serde_json::to_vec(&meta)?,
+      )
+      .await?;
+
+    Ok(etag)
+  }
+
+  /// Uploads a part from an existing object, `range` is an inclusive byte range of the source
+  #[instrument]
+  pub async fn upload_part_copy(
+    &self,
+    source: &CopySource,
+    range: Option<(u64, u64)>,
+    bucket: &String,
+    key: &str,
+    upload_id: &str,
+    part_number: u16,
+  ) -> Result<PartInfo> {
+    let (source_bucket, source_key) = (&source.bucket, &source.key);
+    let source = self.object_path(source_bucket, source_key).await?;
+    let size = match self.file_info(&source).await {
+      Ok(info) => info.size,
+      Err(e) if is_missing(&e) => {
+        s3_bail!(NoSuchKey, "Object {source_key} not found")
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let (first, last) = range.unwrap_or((0, size.saturating_sub(1)));
+    if range.is_some() && (first > last || last >= size) {
+      s3_bail!(
+        InvalidArgument,
+        "Range {first}-{last} is not within the {size} bytes of {source_key}"
+      );
+    }
+
+    // only the range is read, the bytes before it are skipped by the storage
+    let mut reader = self
+      .read_object(source_bucket, source_key, range.map(|_| (first, last)))
+      .await?;
+
+    let etag = self
+      .upload_part(bucket, key, upload_id, part_number, &mut reader)
+      .await?;
+    let dir = self.upload_dir(bucket, key, upload_id).await?;
+    let info = self.file_info(&dir.join(part_number.to_string())).await?;
+
+    Ok(PartInfo {
+      part_number,
+      etag,
+      size: info.size,
+      last_modified: info.modified,
+    })
+  }
+
+  #[instrument]
+  pub async fn list_parts(
+    &self,
+    bucket: &String,
+    key: &str,
+    upload_id: &str,
+  ) -> Result<Vec<PartInfo>> {
+    let dir = self.upload_dir(bucket, key, upload_id).await?;
+
+    let mut parts = Vec::new();
+    for name in upload_file(self.list_dir(&dir).await, upload_id)? {
+      let Some(part_number) = name
+        .strip_suffix(".json")
+        .and_then(|number| number.parse::<u16>().ok())
+      else {
+        continue;
+      };
+
+      let meta = upload_file(self.read_file(&dir.join(&name)).await, upload_id)?;
+      let meta: PartMeta = serde_json::from_slice(&meta)?;
+      let info = upload_file(
+        self.file_info(&dir.join(part_number.to_string())).await,
+        upload_id,
+      )?;
+      parts.push(PartInfo {
+        part_number,
+        etag: meta.etag,
+        size: info.size,
+        last_modified: info.modified,
+      });
+    }
+    parts.sort_unstable_by_key(|part| part.part_number);
+
+    Ok(parts)
+  }
+
+  /// Concatenates the given parts into the object which gets a composite etag
+  #[instrument]
+  pub async fn complete_multipart_upload(
+    &self,
+    bucket: &String,
+    key: &str,
+    upload_id: &str,
+    parts: &[(u16, String)],
+    condition: Option<&WriteCondition>,
+  ) -> Result<ObjectInfo> {
+    let _upload = self.lock_upload(upload_id).await;
+    let dir = self.upload_dir(bucket, key, upload_id).await?;
+    let info = self.upload_info(&dir).await?;
+    if parts.is_empty() {
+      s3_bail!(MalformedXml, "At least one part must be specified");
+    }
+
+    let stored: HashMap<u16, PartInfo> = self
+      .list_parts(bucket, key, upload_id)
+      .await?
+      .into_iter()
+      .map(|part| (part.part_number, part))
+      .collect();
+
+    let mut md5 = Md5::new();
+    for (i, (part_number, etag)) in parts.iter().enumerate() {
+      if i > 0 && *part_number <= parts[i - 1].0 {
+        s3_bail!(
+          InvalidPartOrder,
+          "Parts must be specified in ascending order"
+        );
+      }
+      let Some(part) = stored.get(part_number) else {
+        s3_bail!(InvalidPart, "Part {part_number} has not been uploaded");
+      };
+      if part.etag != etag.trim_matches('"') {
+        s3_bail!(InvalidPart, "ETag of part {part_number} does not match");
+      }
+      if i < parts.len() - 1 && part.size < MIN_PART_SIZE {
+        s3_bail!(
+          EntityTooSmall,
+          "Part {part_number} is smaller than the minimum allowed size"
+        );
+      }
+
+      md5.update(hex::decode(&part.etag).status(StatusCode::INTERNAL_SERVER_ERROR)?);
+    }
+
+    let object = self.object_path(bucket, key).await?;
+    let _lock = self.locks.lock(bucket, key).await;
+    self.check_write(bucket, key, condition).await?;
+
+    if let Some(parent) = object.parent() {
+      key_conflict(self.create_dir(parent).await, key)?;
+    }
+
+    let paths = parts
+      .iter()
+      .map(|(part_number, _)| dir.join(part_number.to_string()))
+      .collect();
+    let mut reader = PartsReader::new(self.storage.clone(), paths);
+    key_conflict(self.stream_write_file(&object, &mut reader).await, key)?;
+    let size = self.file_info(&object).await?.size;
+
+    let etag = format!("{}-{}", hex::encode(md5.finalize()), parts.len());
+    let sizes = parts
+      .iter()
+      .map(|(part_number, _)| stored[part_number].size)
+      .collect();
+    let object = self
+      .save_object(bucket, key, size, etag, info.metadata, sizes)
+      .await?;
+
+    // the parts are only removed once the object is recorded so a failed
+    // completion can be retried, leftovers are collected with stale uploads
+    if let Err(e) = self.delete_dir(&dir).await {
+      tracing::warn!("Failed to remove the parts of completed upload {upload_id}: {e}");
+    }
+
+    Ok(object)
+  }
+
+  #[instrument]
+  pub async fn abort_multipart_upload(
+    &self,
+    bucket: &String,
+    key: &str,
+    upload_id: &str,
+  ) -> Result<()> {
+    let _upload = self.lock_upload(upload_id).await;
+    let dir = self.upload_dir(bucket, key, upload_id).await?;
+    self.delete_dir(&dir).await?;
+
+    Ok(())
+  }
+
+  /// Lists in progress uploads of a bucket ordered by key and initiation time
+  #[instrument]
+  pub async fn list_multipart_uploads(
+    &self,
+    bucket: &String,
+    options: &ListOptions,
+    upload_id_marker: Option<&str>,
+  ) -> Result<UploadPage> {
+    self.bucket(bucket).await?;
+
+    let mut uploads: Vec<_> = self
+      .uploads()
+      .await?
+      .into_iter()
+      .filter(|upload| {
+        upload.info.bucket == *bucket && upload.info.key.starts_with(&options.prefix)
+      })
+      .collect();
+    uploads.sort_unstable_by(|a, b| {
+      (&a.info.key, a.info.initiated, &a.upload_id).cmp(&(
+        &b.info.key,
+        b.info.initiated,
+        &b.upload_id,
+      ))
+    });
+
+    let delimiter = options.delimiter.as_deref().filter(|d| !d.is_empty());
+    let key_marker = options.start_after.as_deref();
+    let mut passed_upload_id_marker = false;
+    let mut page = UploadPage::default();
+    let mut count = 0;
+
+    for upload in uploads {
+      let key = upload.info.key.as_str();
+      if let Some(marker) = key_marker {
+        if key < marker {
+          continue;
+        }
+        // uploads of the marker key are only listed after the upload id marker
+        if key == marker && !passed_upload_id_marker {
+          passed_upload_id_marker = upload_id_marker == Some(upload.upload_id.as_str());
+          continue;
+        }
+      }
+
+      let common_prefix = delimiter.and_then(|delimiter| {
+        key[options.prefix.len()..]
+          .find(delimiter)
+          .map(|i| key[..options.prefix.len() + i + delimiter.len()].to_string())
+      });
+
+      if let Some(common_prefix) = common_prefix {
+        if key_marker == Some(common_prefix.as_str())
+          || page.common_prefixes.last() == Some(&common_prefix)
+        {
+          continue;
+        }
+        if count == options.max_keys {
+          page.is_truncated = true;
+          break;
+        }
+
+        page.next_key_marker = Some(common_prefix.clone());
+        page.next_upload_id_marker = None;
+        page.common_prefixes.push(common_prefix);
+      } else {
+        if count == options.max_keys {
+          page.is_truncated = true;
+          break;
+        }
+
+        page.next_key_marker = Some(upload.info.key.clone());
+        page.next_upload_id_marker = Some(upload.upload_id.clone());
+        page.uploads.push(upload);
+      }
+      count += 1;
+    }
+
+    Ok(page)
+  }
+
+  /// Aborts all uploads initiated more than `max_age` ago and returns how many were removed
+  #[instrument]
+  pub async fn abort_stale_uploads(&self, max_age: Duration) -> Result<usize> {
+    let deadline = Utc::now() - max_age;
+
+    let mut aborted = 0;
+    for upload in self.uploads().await? {
+      if upload.info.initiated < deadline {
+        let _upload = self.lock_upload(&upload.upload_id).await;
+        // the upload may have been completed or aborted since it was listed
+        let dir = match self
+          .upload_dir(&upload.info.bucket, &upload.info.key, &upload.upload_id)
+          .await
+        {
+          Ok(dir) => dir,
+          Err(e) if e.status == StatusCode::NOT_FOUND => continue,
+          Err(e) => {
+            tracing::error!("Failed to abort stale upload {}: {e}", upload.upload_id);
+            continue;
+          }
+        };
+
+        match self.delete_dir(&dir).await {
+          Ok(()) => aborted += 1,
+          Err(e) if e.kind() == ErrorKind::NotFound => (),
+          Err(e) => tracing::error!("Failed to abort stale upload {}: {e}", upload.upload_id),
+        }
+      }
+    }
+
+    Ok(aborted)
+  }
+
+  /// Periodically aborts uploads that are older than `max_age`
+  pub fn spawn_upload_gc(self, max_age: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(GC_INTERVAL);
+      loop {
+        interval.tick().await;
+        match self.abort_stale_uploads(max_age).await {
+          Ok(0) => (),
+          Ok(aborted) => tracing::info!("Aborted {aborted} stale multipart uploads"),
+          Err(e) => tracing::error!("Failed to abort stale multipart uploads: {e}"),
+        }
+      }
+    })
+  }
+
+  /// Blocks completing and aborting the upload as well as uploading parts
+  pub(super) async fn lock_upload(&self, upload_id: &str) -> OwnedRwLockWriteGuard<()> {
+    self.upload_locks.lock(upload_id, "").await
+  }
+
+  /// Blocks completing and aborting the upload, other parts can still be uploaded
+  async fn lock_upload_shared(&self, upload_id: &str) -> OwnedRwLockReadGuard<()> {
+    self.upload_locks.lock_shared(upload_id, "").await
+  }
+
+  /// Blocks writes to a part of an upload, `file` is the name of the part or its etag file
+  pub(super) async fn lock_part(&self, upload_id: &str, file: &str) -> OwnedRwLockWriteGuard<()> {
+    let part = file.strip_suffix(".json").unwrap_or(file);
+    self.upload_locks.lock(upload_id, part).await
+  }
+
+  #[instrument]
+  async fn uploads(&self) -> Result<Vec<Upload>> {
+    let mut uploads = Vec::new();
+    for upload_id in self.list_dir(&path!(MULTIPART_DIR)).await? {
+      let data = match self
+        .read_file(&path!(MULTIPART_DIR, &upload_id, UPLOAD_INFO))
+        .await
+      {
+        Ok(data) => data,
+        // the upload is still being created or was just completed
+        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+        Err(e) => return Err(e.into()),
+      };
+
+      uploads.push(Upload {
+        upload_id,
+        info: serde_json::from_slice(&data)?,
+      });
+    }
+
+    Ok(uploads)
+  }
+
+  #[instrument]
+  async fn upload_dir(&self, bucket: &String, key: &str, upload_id: &str) -> Result<PathBuf> {
+    // upload ids are uuids, anything else could escape the multipart directory
+    if Uuid::parse_str(upload_id).is_err() {
+      s3_bail!(NoSuchUpload, "Upload {upload_id} not found");
+    }
+
+    let dir = path!(MULTIPART_DIR, upload_id);
+    let info = match self.read_file(&dir.join(UPLOAD_INFO)).await {
+      Ok(data) => serde_json::from_slice::<UploadInfo>(&data)?,
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        s3_bail!(NoSuchUpload, "Upload {upload_id} not found")
+      }
+      Err(e) => return Err(e.into()),
+    };
+
+    if info.bucket != *bucket || info.key != key {
+      s3_bail!(NoSuchUpload, "Upload {upload_id} not found");
+    }
+
+    Ok(dir)
+  }
+
+  #[instrument]
+  async fn upload_info(&self, dir: &Path) -> Result<UploadInfo> {
+    Ok(serde_json::from_slice(
+      &self.read_file(&dir.join(UPLOAD_INFO)).await?,
+    )?)
+  }
+}
+
+/// Files of an upload disappear when it is completed or aborted concurrently
+fn upload_file<T>(result: io::Result<T>, upload_id: &str) -> Result<T> {
+  match result {
+    Err(e) if e.kind() == ErrorKind::NotFound => {
+      s3_bail!(NoSuchUpload, "Upload {upload_id} not found")
+    }
+    result => Ok(result?),
+  }
+}
+
+/// Reads the staged parts one after another, so only one part is open at a time
+struct PartsReader {
+  storage: Arc<dyn Storage + Send + Sync>,
+  paths: VecDeque<PathBuf>,
+  opening: Option<BoxFuture<'static, io::Result<Box<dyn AsyncRead + Unpin + Send>>>>,
+  current: Option<Box<dyn AsyncRead + Unpin + Send>>,
+}
+
+impl PartsReader {
+  fn new(storage: Arc<dyn Storage + Send + Sync>, paths: VecDeque<PathBuf>) -> Self {
+    Self {
+      storage,
+      paths,
+      opening: None,
+      current: None,
+    }
+  }
+}
+
+impl AsyncRead for PartsReader {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    loop {
+      if let Some(current) = &mut this.current {
+        let filled = buf.filled().len();
+        ready!(Pin::new(current).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+          return Poll::Ready(Ok(()));
+        }
+        // end of this part
+        this.current = None;
+      } else if let Some(opening) = &mut this.opening {
+        this.current = Some(ready!(opening.as_mut().poll(cx))?);
+        this.opening = None;
+      } else if let Some(path) = this.paths.pop_front() {
+        let storage = this.storage.clone();
+        this.opening = Some(Box::pin(
+          async move { storage.stream_read_file(&path).await },
+        ));
+      } else {
+        return Poll::Ready(Ok(()));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+  use crate::s3::interface::test::interface;
+
+  #[tokio::test]
+  async fn test_multipart_upload() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let metadata = ObjectMetadata {
+      content_type: Some("text/plain".into()),
+      ..Default::default()
+    };
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "dir/big", metadata)
+      .await?;
+
+    let first = vec![b'a'; MIN_PART_SIZE as usize];
+    let etag_1 = interface
+      .upload_part(&bucket, "dir/big", &upload_id, 1, &mut &first[..])
+      .await?;
+    let etag_2 = interface
+      .upload_part(&bucket, "dir/big", &upload_id, 2, &mut &b"end"[..])
+      .await?;
+
+    let parts = interface.list_parts(&bucket, "dir/big", &upload_id).await?;
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[1].size, 3);
+
+    let info = interface
+      .complete_multipart_upload(
+        &bucket,
+        "dir/big",
+        &upload_id,
+        &[(1, format!("\"{etag_1}\"")), (2, etag_2)],
+        None,
+      )
+      .await?;
+    assert!(info.etag.ends_with("-2"));
+
+    let (info, mut reader) = interface.get_object(&bucket, "dir/big").await?;
+    assert_eq!(info.size, MIN_PART_SIZE + 3);
+    assert!(info.etag.ends_with("-2"));
+    assert_eq!(info.metadata.content_type.as_deref(), Some("text/plain"));
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    assert!(data.ends_with(b"aend"));
+    assert_eq!(info.parts, vec![MIN_PART_SIZE, 3]);
+
+    // the second part can be read on its own
+    let mut data = Vec::new();
+    interface
+      .read_object(&bucket, "dir/big", Some((MIN_PART_SIZE, MIN_PART_SIZE + 2)))
+      .await?
+      .read_to_end(&mut data)
+      .await?;
+    assert_eq!(data, b"end");
+
+    // the upload is gone after completion
+    assert!(
+      interface
+        .list_parts(&bucket, "dir/big", &upload_id)
+        .await
+        .is_err()
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_multipart_upload_invalid_parts() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "key", ObjectMetadata::default())
+      .await?;
+    let etag_1 = interface
+      .upload_part(&bucket, "key", &upload_id, 1, &mut &b"small"[..])
+      .await?;
+    let etag_2 = interface
+      .upload_part(&bucket, "key", &upload_id, 2, &mut &b"end"[..])
+      .await?;
+
+    // too small
+    assert!(
+      interface
+        .complete_multipart_upload(
+          &bucket,
+          "key",
+          &upload_id,
+          &[(1, etag_1.clone()), (2, etag_2.clone())],
+          None
+        )
+        .await
+        .is_err()
+    );
+    // wrong order
+    assert!(
+      interface
+        .complete_multipart_upload(
+          &bucket,
+          "key",
+          &upload_id,
+          &[(2, etag_2), (1, etag_1)],
+          None
+        )
+        .await
+        .is_err()
+    );
+    // wrong key
+    assert!(
+      interface
+        .list_parts(&bucket, "other", &upload_id)
+        .await
+        .is_err()
+    );
+
+    interface
+      .abort_multipart_upload(&bucket, "key", &upload_id)
+      .await?;
+    assert!(
+      interface
+        .abort_multipart_upload(&bucket, "key", &upload_id)
+        .await
+        .is_err()
+    );
+    assert!(
+      interface
+        .abort_multipart_upload(&bucket, "key", "../buckets")
+        .await
+        .is_err()
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_upload_part_copy() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    interface
+      .put_object(
+        &bucket,
+        "source",
+        ObjectMetadata::default(),
+        &mut &b"Hello, world!"[..],
+        None,
+      )
+      .await?;
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "key", ObjectMetadata::default())
+      .await?;
+    let source = CopySource {
+      bucket: bucket.clone(),
+      key: "source".into(),
+    };
+
+    let part = interface
+      .upload_part_copy(&source, Some((7, 11)), &bucket, "key", &upload_id, 1)
+      .await?;
+    assert_eq!(part.size, 5);
+    assert!(
+      interface
+        .upload_part_copy(&source, Some((7, 13)), &bucket, "key", &upload_id, 2)
+        .await
+        .is_err()
+    );
+
+    interface
+      .complete_multipart_upload(&bucket, "key", &upload_id, &[(1, part.etag)], None)
+      .await?;
+    let (_, mut reader) = interface.get_object(&bucket, "key").await?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    assert_eq!(data, b"world");
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_list_multipart_uploads() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let first = interface
+      .create_multipart_upload(&bucket, "a", ObjectMetadata::default())
+      .await?;
+    let second = interface
+      .create_multipart_upload(&bucket, "a", ObjectMetadata::default())
+      .await?;
+    interface
+      .create_multipart_upload(&bucket, "dir/b", ObjectMetadata::default())
+      .await?;
+    interface
+      .create_multipart_upload(&bucket, "dir/c", ObjectMetadata::default())
+      .await?;
+
+    let options = ListOptions {
+      delimiter: Some("/".into()),
+      max_keys: 2,
+      ..Default::default()
+    };
+    let page = interface
+      .list_multipart_uploads(&bucket, &options, None)
+      .await?;
+    let ids: Vec<_> = page.uploads.iter().map(|u| u.upload_id.as_str()).collect();
+    assert_eq!(ids, vec![first.as_str(), second.as_str()]);
+    assert!(page.is_truncated);
+
+    let options = ListOptions {
+      start_after: page.next_key_marker,
+      ..options
+    };
+    let page = interface
+      .list_multipart_uploads(&bucket, &options, page.next_upload_id_marker.as_deref())
+      .await?;
+    assert!(page.uploads.is_empty());
+    assert_eq!(page.common_prefixes, vec!["dir/"]);
+    assert!(!page.is_truncated);
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_upload_part_during_abort() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "key", ObjectMetadata::default())
+      .await?;
+
+    // a part upload waits for the abort and does not recreate the removed upload
+    let lock = interface.lock_upload(&upload_id).await;
+    let part = tokio::spawn({
+      let (interface, bucket, upload_id) = (interface.clone(), bucket.clone(), upload_id.clone());
+      async move {
+        interface
+          .upload_part(&bucket, "key", &upload_id, 1, &mut &b"data"[..])
+          .await
+      }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!part.is_finished());
+    let dir = temp_dir.join(MULTIPART_DIR).join(&upload_id);
+    tokio::fs::remove_dir_all(&dir).await?;
+    drop(lock);
+
+    let err = part.await.unwrap().unwrap_err();
+    assert_eq!(err.status, StatusCode::NOT_FOUND);
+    assert!(!tokio::fs::try_exists(&dir).await?);
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_abort_stale_uploads() -> Result<()> {
+    let (interface, temp_dir) = interface().await;
+    let bucket = "bucket".to_string();
+
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "key", ObjectMetadata::default())
+      .await?;
+    interface
+      .upload_part(&bucket, "key", &upload_id, 1, &mut &b"data"[..])
+      .await?;
+
+    assert_eq!(
+      interface
+        .abort_stale_uploads(Duration::from_secs(60))
+        .await?,
+      0
+    );
+    assert_eq!(interface.abort_stale_uploads(Duration::ZERO).await?, 1);
+    assert!(
+      interface
+        .list_parts(&bucket, "key", &upload_id)
+        .await
+        .is_err()
+    );
+
+    // an upload that is removed while the collection waits for it does not stop it
+    let mut upload_ids = Vec::new();
+    for key in ["a", "b"] {
+      upload_ids.push(
+        interface
+          .create_multipart_upload(&bucket, key, ObjectMetadata::default())
+          .await?,
+      );
+    }
+    let lock = interface.lock_upload(&upload_ids[0]).await;
+    let gc = tokio::spawn({
+      let interface = interface.clone();
+      async move { interface.abort_stale_uploads(Duration::ZERO).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tokio::fs::remove_dir_all(temp_dir.join(MULTIPART_DIR).join(&upload_ids[0])).await?;
+    drop(lock);
+    assert_eq!(gc.await.unwrap()?, 1);
+    assert!(
+      interface
+        .list_parts(&bucket, "b", &upload_ids[1])
+        .await
+        .is_err()
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+}
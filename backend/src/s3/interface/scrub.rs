@@ -0,0 +1,279 @@
+use std::{path::PathBuf, time::Duration};
+
+use centaurus::{error::Result, path};
+use chrono::Utc;
+use metrics::{Unit, counter, describe_counter, describe_gauge, gauge};
+use tokio::{task::JoinHandle, time::Instant};
+use tracing::instrument;
+
+use crate::s3::{
+  BUCKET_DIR, MULTIPART_DIR,
+  interface::S3Interface,
+  storage::{ScrubReport, walk::KeyWalker},
+};
+
+/// Metrics of the scrubber, named like the http metrics of the app
+#[derive(Debug, Clone)]
+pub struct ScrubMetrics {
+  prefix: String,
+  labels: Vec<(String, String)>,
+}
+
+impl ScrubMetrics {
+  pub fn new(prefix: String, labels: Vec<(String, String)>) -> Self {
+    describe_gauge!(
+      format!("{prefix}_scrub_progress"),
+      "Share of the files checked by the running scrub"
+    );
+    describe_counter!(
+      format!("{prefix}_scrub_files_total"),
+      Unit::Count,
+      "Total number of files checked by the scrubber"
+    );
+    describe_counter!(
+      format!("{prefix}_scrub_damaged_total"),
+      Unit::Count,
+      "Total number of corrupt, missing or stale copies and shards found by the scrubber"
+    );
+    describe_counter!(
+      format!("{prefix}_scrub_repaired_total"),
+      Unit::Count,
+      "Total number of copies and shards repaired by the scrubber"
+    );
+    describe_gauge!(
+      format!("{prefix}_scrub_last_completed"),
+      Unit::Seconds,
+      "Unix time the last scrub completed at"
+    );
+
+    Self { prefix, labels }
+  }
+
+  fn progress(&self, checked: usize, total: usize) {
+    let progress = if total == 0 {
+      1.0
+    } else {
+      checked as f64 / total as f64
+    };
+    gauge!(format!("{}_scrub_progress", self.prefix), &self.labels).set(progress);
+  }
+
+  fn checked(&self, report: &ScrubReport) {
+    counter!(format!("{}_scrub_files_total", self.prefix), &self.labels).increment(1);
+    counter!(format!("{}_scrub_damaged_total", self.prefix), &self.labels)
+      .increment(report.damaged as u64);
+    counter!(
+      format!("{}_scrub_repaired_total", self.prefix),
+      &self.labels
+    )
+    .increment(report.repaired as u64);
+  }
+
+  fn completed(&self) {
+    gauge!(
+      format!("{}_scrub_last_completed", self.prefix),
+      &self.labels
+    )
+    .set(Utc::now().timestamp() as f64);
+  }
+}
+
+/// File to scrub, it is locked while it is scrubbed so a repair can not overwrite
+/// a concurrent write or restore a file that was just removed
+struct ScrubTarget {
+  path: PathBuf,
+  owner: Owner,
+}
+
+enum Owner {
+  Object { bucket: String, key: String },
+  Upload { upload_id: String, file: String },
+}
+
+impl S3Interface {
+  /// Periodically scrubs all files of redundant storage, the first scrub starts
+  /// one interval after startup
+  pub fn spawn_scrubber(self, interval: Duration, metrics: ScrubMetrics) -> JoinHandle<()> {
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+      loop {
+        interval.tick().await;
+        match self.scrub(&metrics).await {
+          Ok(report) if report.damaged > 0 => tracing::warn!(
+            "Scrub found {} damaged copies and repaired {} of them",
+            report.damaged,
+            report.repaired
+          ),
+          Ok(_) => tracing::info!("Scrub found no damaged copies"),
+          Err(e) => tracing::error!("Failed to scrub storage: {e}"),
+        }
+      }
+    })
+  }
+
+  /// Verifies all objects and multipart uploads and repairs damaged copies
+  #[instrument(skip(metrics))]
+  pub async fn scrub(&self, metrics: &ScrubMetrics) -> Result<ScrubReport> {
+    let targets = self.scrub_targets().await?;
+    metrics.progress(0, targets.len());
+
+    let mut total = ScrubReport::default();
+    for (checked, target) in targets.iter().enumerate() {
+      let (_locks, tracked) = match &target.owner {
+        Owner::Object { bucket, key } => {
+          let lock = self.locks.lock(bucket, key).await;
+          // an object without a record was deleted while some copies were degraded
+          let tracked = self.db.object().get_object(bucket, key).await?.is_some();
+          (vec![lock], tracked)
+        }
+        // the upload can not be completed or aborted while its parts are repaired
+        Owner::Upload { upload_id, file } => (
+          vec![
+            self.lock_upload(upload_id).await,
+            self.lock_part(upload_id, file).await,
+          ],
+          true,
+        ),
+      };
+      match self.scrub_file(&target.path, tracked).await {
+        Ok(report) => {
+          metrics.checked(&report);
+          total.damaged += report.damaged;
+          total.repaired += report.repaired;
+        }
+        Err(e) => tracing::error!("Failed to scrub {}: {e}", target.path.display()),
+      }
+      metrics.progress(checked + 1, targets.len());
+    }
+
+    metrics.completed();
+    Ok(total)
+  }
+
+  async fn scrub_targets(&self) -> Result<Vec<ScrubTarget>> {
+    let mut targets = Vec::new();
+    for bucket in self.list_dir(&path!(BUCKET_DIR)).await? {
+      let mut walker = KeyWalker::new(&*self.storage, path!(BUCKET_DIR, &bucket), "", None);
+      while let Some(key) = walker.next().await? {
+        targets.push(ScrubTarget {
+          path: path!(BUCKET_DIR, &bucket, &key),
+          owner: Owner::Object {
+            bucket: bucket.clone(),
+            key,
+          },
+        });
+      }
+    }
+
+    let mut walker = KeyWalker::new(&*self.storage, path!(MULTIPART_DIR), "", None);
+    while let Some(file) = walker.next().await? {
+      let Some((upload_id, name)) = file.split_once('/') else {
+        continue;
+      };
+      targets.push(ScrubTarget {
+        path: path!(MULTIPART_DIR, &file),
+        owner: Owner::Upload {
+          upload_id: upload_id.to_string(),
+          file: name.to_string(),
+        },
+      });
+    }
+    Ok(targets)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tokio::{fs, io::AsyncReadExt};
+
+  use crate::{
+    config::EnvConfig,
+    db::test::test_db,
+    s3::{interface::BucketSettings, storage::StorageType},
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_scrub() -> Result<()> {
+    let temp_dir = std::env::temp_dir().join(format!("sagittarius-{}", uuid::Uuid::new_v4()));
+    let config = EnvConfig {
+      storage_type: StorageType::Mirror,
+      storage_paths: vec![temp_dir.join("a"), temp_dir.join("b")],
+      ..Default::default()
+    };
+    let interface = config
+      .storage_type
+      .storage(&config, test_db().await)
+      .await?;
+    let bucket = "bucket".to_string();
+    interface
+      .create_bucket(&bucket, None, "us-east-1", BucketSettings::default())
+      .await?;
+    interface
+      .put_object(
+        &bucket,
+        "dir/key",
+        Default::default(),
+        &mut &b"data"[..],
+        None,
+      )
+      .await?;
+
+    let metrics = ScrubMetrics::new("test".into(), vec![]);
+    assert_eq!(interface.scrub(&metrics).await?, ScrubReport::default());
+
+    fs::remove_file(temp_dir.join("a").join(BUCKET_DIR).join("bucket/dir/key")).await?;
+    assert_eq!(
+      interface.scrub(&metrics).await?,
+      ScrubReport {
+        damaged: 1,
+        repaired: 1
+      }
+    );
+    let (_, mut reader) = interface.get_object(&bucket, "dir/key").await?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    assert_eq!(data, b"data");
+
+    // a copy that missed the delete of its object is removed
+    interface
+      .db
+      .object()
+      .delete_object(&bucket, "dir/key")
+      .await?;
+    fs::remove_file(temp_dir.join("a").join(BUCKET_DIR).join("bucket/dir/key")).await?;
+    assert_eq!(
+      interface.scrub(&metrics).await?,
+      ScrubReport {
+        damaged: 1,
+        repaired: 1
+      }
+    );
+    assert!(!fs::try_exists(temp_dir.join("b").join(BUCKET_DIR).join("bucket/dir/key")).await?);
+
+    // parts are not scrubbed while their upload is being completed or aborted
+    let upload_id = interface
+      .create_multipart_upload(&bucket, "upload", Default::default())
+      .await?;
+    interface
+      .upload_part(&bucket, "upload", &upload_id, 1, &mut &b"part"[..])
+      .await?;
+    let upload = interface.lock_upload(&upload_id).await;
+    let scrub = tokio::spawn({
+      let interface = interface.clone();
+      async move {
+        interface
+          .scrub(&ScrubMetrics::new("test".into(), vec![]))
+          .await
+      }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!scrub.is_finished());
+    drop(upload);
+    assert_eq!(scrub.await.unwrap()?, ScrubReport::default());
+
+    fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+}
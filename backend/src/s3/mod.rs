@@ -1,22 +1,52 @@
-use crate::{config::EnvConfig, router_extension};
-use axum::Router;
+use crate::{config::EnvConfig, db::Connection, macros::DualRouterExt, router_extension};
+use axum::{Extension, Router, extract::DefaultBodyLimit, middleware};
 
-mod auth;
+pub mod auth;
+pub mod checksum;
+pub mod error;
+mod hash;
 mod header;
 pub mod interface;
 mod operations;
+pub mod region;
 pub mod storage;
+mod virtual_host;
 
 const BUCKET_DIR: &str = "buckets";
+/// staging area for parts of multipart uploads
+const MULTIPART_DIR: &str = "multipart";
 
-pub fn router() -> Router {
-  operations::router()
+pub fn router(config: &EnvConfig) -> Router {
+  // bodies are streamed and limited by the handlers, xml bodies and form uploads
+  // enforce their own limit
+  let router = operations::router()
+    .layer(middleware::from_fn(error::error_layer))
+    .layer(DefaultBodyLimit::disable());
+
+  // route layers only run after routing, so the host is resolved in an outer router
+  Router::new()
+    .fallback_service(router)
+    .layer(middleware::from_fn_with_state(
+      virtual_host::VirtualHost::new(config),
+      virtual_host::virtual_host,
+    ))
 }
 
 router_extension!(
-  async fn s3(self, config: &EnvConfig) -> Self {
+  async fn s3(
+    self,
+    interface: interface::S3Interface,
+    config: &EnvConfig,
+    db: &Connection,
+  ) -> Self {
     use interface::interface;
 
-    self.interface(config).await
+    self
+      .interface(interface)
+      .await
+      .layer(Extension(
+        auth::access_key::AccessKeys::init(config, db).await,
+      ))
+      .layer(Extension(region::Regions::new(config)))
   }
 );
@@ -0,0 +1,148 @@
+use std::time::SystemTime;
+
+use axum::extract::Path;
+use axum_extra::headers::{HeaderMapExt, LastModified};
+use centaurus::req::xml::Xml;
+use http::HeaderMap;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::{
+  s3::{
+    auth::{Identity, S3Auth},
+    checksum::{Checksum, ChecksumAlgorithm},
+    error::Result,
+    interface::S3Interface,
+  },
+  s3_bail,
+};
+
+const OBJECT_ATTRIBUTES: &str = "x-amz-object-attributes";
+const STORAGE_CLASS: &str = "STANDARD";
+
+#[instrument(skip(headers))]
+pub(super) async fn get_object_attributes(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<(HeaderMap, Xml<GetObjectAttributesResponse>)> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} getting attributes of {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to get object attributes");
+    }
+  }
+
+  let attributes = requested_attributes(&headers)?;
+  let info = interface.head_object(&bucket, &key).await?;
+  let wants = |attribute: &str| attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute));
+
+  let mut headers = HeaderMap::new();
+  headers.typed_insert(LastModified::from(SystemTime::from(info.last_modified)));
+
+  Ok((
+    headers,
+    Xml(GetObjectAttributesResponse {
+      etag: wants("ETag").then(|| info.etag.clone()),
+      checksum: info
+        .metadata
+        .checksum
+        .as_ref()
+        .filter(|_| wants("Checksum"))
+        .map(ChecksumResult::from),
+      object_parts: (wants("ObjectParts") && !info.parts.is_empty()).then_some(ObjectParts {
+        parts_count: info.parts.len(),
+      }),
+      storage_class: wants("StorageClass").then(|| STORAGE_CLASS.to_string()),
+      object_size: wants("ObjectSize").then_some(info.size),
+    }),
+  ))
+}
+
+fn requested_attributes(headers: &HeaderMap) -> Result<Vec<String>> {
+  let mut attributes = Vec::new();
+  for value in headers.get_all(OBJECT_ATTRIBUTES) {
+    let Ok(value) = value.to_str() else {
+      s3_bail!(InvalidArgument, "Invalid {OBJECT_ATTRIBUTES} header");
+    };
+    attributes.extend(
+      value
+        .split(',')
+        .map(|attribute| attribute.trim().to_string())
+        .filter(|attribute| !attribute.is_empty()),
+    );
+  }
+
+  if attributes.is_empty() {
+    s3_bail!(
+      InvalidArgument,
+      "The {OBJECT_ATTRIBUTES} header specifying the attributes to be retrieved is either missing or empty"
+    );
+  }
+  Ok(attributes)
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename = "GetObjectAttributesResponse", rename_all = "PascalCase")]
+pub(super) struct GetObjectAttributesResponse {
+  #[serde(rename = "ETag")]
+  etag: Option<String>,
+  checksum: Option<ChecksumResult>,
+  object_parts: Option<ObjectParts>,
+  storage_class: Option<String>,
+  object_size: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ChecksumResult {
+  #[serde(rename = "ChecksumCRC32")]
+  crc32: Option<String>,
+  #[serde(rename = "ChecksumCRC32C")]
+  crc32c: Option<String>,
+  #[serde(rename = "ChecksumSHA1")]
+  sha1: Option<String>,
+  #[serde(rename = "ChecksumSHA256")]
+  sha256: Option<String>,
+  checksum_type: String,
+}
+
+impl From<&Checksum> for ChecksumResult {
+  fn from(checksum: &Checksum) -> Self {
+    let value = |algorithm| (checksum.algorithm == algorithm).then(|| checksum.value.clone());
+    Self {
+      crc32: value(ChecksumAlgorithm::Crc32),
+      crc32c: value(ChecksumAlgorithm::Crc32c),
+      sha1: value(ChecksumAlgorithm::Sha1),
+      sha256: value(ChecksumAlgorithm::Sha256),
+      checksum_type: "FULL_OBJECT".into(),
+    }
+  }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ObjectParts {
+  parts_count: usize,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_requested_attributes() {
+    let mut headers = HeaderMap::new();
+    assert!(requested_attributes(&headers).is_err());
+
+    headers.append(OBJECT_ATTRIBUTES, "ETag, Checksum".parse().unwrap());
+    headers.append(OBJECT_ATTRIBUTES, "ObjectSize".parse().unwrap());
+    assert_eq!(
+      requested_attributes(&headers).unwrap(),
+      vec!["ETag", "Checksum", "ObjectSize"]
+    );
+  }
+}
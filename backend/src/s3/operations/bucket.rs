@@ -3,14 +3,24 @@ use axum::{
   extract::{Path, Query},
   routing::{delete, get, put},
 };
-use centaurus::{bail, error::Result, req::xml::Xml};
+use axum_extra::headers::HeaderMapExt;
+use centaurus::req::xml::Xml;
+use chrono::SecondsFormat;
 use http::{HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
+use uuid::Uuid;
 
-use crate::s3::{
-  auth::{Identity, S3Auth},
-  interface::S3Interface,
+use crate::{
+  db::Connection,
+  s3::{
+    auth::{Identity, S3Auth},
+    error::Result,
+    header::AwzBucketObjectLockEnabled,
+    interface::{BucketSettings, S3Interface},
+    region::Regions,
+  },
+  s3_bail,
 };
 
 pub fn router() -> Router {
@@ -22,23 +32,45 @@ pub fn router() -> Router {
     .route("/", get(list_buckets))
 }
 
-/// TODO: Handling of additional header options
-#[instrument]
+/// TODO: Handling of acl header options
+#[instrument(skip(headers))]
 async fn create_bucket(
   interface: S3Interface,
+  regions: Regions,
   Path(bucket): Path<String>,
-  S3Auth { identity, .. }: S3Auth<Option<Xml<CreateBucketConfiguration>>>,
+  headers: HeaderMap,
+  S3Auth { identity, body, .. }: S3Auth<Option<Xml<CreateBucketConfiguration>>>,
 ) -> Result<HeaderMap> {
-  match identity {
+  let owner = match identity {
     Identity::AccessKey(key) => {
       tracing::info!("AccessKey {key} creating bucket {bucket}");
+      Some(key.user)
     }
     Identity::Anonymous => {
       tracing::warn!("Anonymous access to create bucket");
+      None
     }
+  };
+
+  let region = body
+    .and_then(|Xml(config)| config.location_constraint)
+    .filter(|region| !region.is_empty())
+    .unwrap_or_else(|| regions.default.clone());
+  if !regions.is_allowed(&region) {
+    s3_bail!(
+      InvalidLocationConstraint,
+      "The specified location-constraint {region} is not valid"
+    );
   }
 
-  interface.create_bucket(&bucket).await?;
+  let settings = BucketSettings {
+    object_lock_enabled: headers
+      .typed_get::<AwzBucketObjectLockEnabled>()
+      .is_some_and(|enabled| enabled.0),
+  };
+  interface
+    .create_bucket(&bucket, owner, &region, settings)
+    .await?;
 
   let mut headers = HeaderMap::new();
   headers.insert("Location", format!("/{bucket}").parse()?);
@@ -48,7 +80,10 @@ async fn create_bucket(
 
 /// TODO: Handling of additional configuration options
 #[derive(Deserialize, Debug)]
-struct CreateBucketConfiguration {}
+#[serde(rename_all = "PascalCase")]
+struct CreateBucketConfiguration {
+  location_constraint: Option<String>,
+}
 
 #[instrument]
 async fn delete_bucket(
@@ -70,6 +105,45 @@ async fn delete_bucket(
   Ok(StatusCode::NO_CONTENT)
 }
 
+#[instrument]
+pub(super) async fn get_bucket_location(
+  interface: S3Interface,
+  Path(bucket): Path<String>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<LocationConstraint>> {
+  match identity {
+    Identity::AccessKey(key) => {
+      tracing::info!("AccessKey {key} getting location of bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to get bucket location");
+    }
+  }
+
+  let bucket = interface.bucket(&bucket).await?;
+
+  Ok(Xml(LocationConstraint::new(bucket.region)))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(super) struct LocationConstraint {
+  #[serde(rename = "#text")]
+  region: String,
+}
+
+impl LocationConstraint {
+  /// S3 reports buckets in us-east-1 with an empty location constraint,
+  /// clients map it back to that region
+  fn new(region: String) -> Self {
+    let region = if region == "us-east-1" {
+      String::new()
+    } else {
+      region
+    };
+    Self { region }
+  }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 struct ListQuery {
@@ -80,58 +154,51 @@ struct ListQuery {
 #[instrument]
 async fn list_buckets(
   interface: S3Interface,
+  db: Connection,
   Query(ListQuery {
     prefix,
     max_buckets,
   }): Query<ListQuery>,
   S3Auth { identity, .. }: S3Auth,
 ) -> Result<Xml<ListAllMyBucketsResult>> {
-  match identity {
+  let owner = match identity {
     Identity::AccessKey(key) => {
       tracing::info!("AccessKey {key} listing buckets");
+      owner(&db, key.user).await
     }
     Identity::Anonymous => {
       tracing::warn!("Anonymous access to list buckets");
+      None
     }
-  }
-
-  let buckets = interface.list_buckets().await?;
-
-  let buckets: Vec<String> = if let Some(prefix) = prefix.clone() {
-    buckets
-      .into_iter()
-      .filter(|b| b.starts_with(&prefix))
-      .collect()
-  } else {
-    buckets
   };
 
-  let buckets: Vec<String> = if let Some(max) = max_buckets {
-    if !(1..=10000).contains(&max) {
-      bail!(BAD_REQUEST, "max-buckets must be between 1 and 10000");
-    }
-
-    buckets.into_iter().take(max).collect()
-  } else {
-    buckets
-  };
+  if let Some(max) = max_buckets
+    && !(1..=10000).contains(&max)
+  {
+    s3_bail!(InvalidArgument, "max-buckets must be between 1 and 10000");
+  }
 
-  let buckets: Vec<Bucket> = buckets
+  let buckets: Vec<Bucket> = interface
+    .list_buckets()
+    .await?
     .into_iter()
-    .map(|name| Bucket {
-      name,
-      creation_date: "".into(), // TODO: fill with actual creation date if available
-      bucket_region: None,
+    .filter(|bucket| {
+      prefix
+        .as_ref()
+        .is_none_or(|prefix| bucket.name.starts_with(prefix))
+    })
+    .take(max_buckets.unwrap_or(usize::MAX))
+    .map(|bucket| Bucket {
+      name: bucket.name,
+      creation_date: bucket.created.to_rfc3339_opts(SecondsFormat::Millis, true),
+      bucket_region: Some(bucket.region),
     })
     .collect();
 
   Ok(Xml(ListAllMyBucketsResult {
     buckets: Buckets { buckets },
     prefix,
-    owner: Owner {
-      id: "owner-id".into(),
-      display_name: "owner-display-name".into(),
-    },
+    owner,
   }))
 }
 
@@ -140,7 +207,7 @@ async fn list_buckets(
 struct ListAllMyBucketsResult {
   buckets: Buckets,
   prefix: Option<String>,
-  owner: Owner,
+  owner: Option<Owner>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -157,10 +224,43 @@ struct Bucket {
   bucket_region: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "PascalCase")]
-struct Owner {
+pub(super) struct Owner {
   #[serde(rename = "ID")]
   id: String,
   display_name: String,
 }
+
+/// Owner reported for a user, deleted users have no owner to report
+pub(super) async fn owner(db: &Connection, user: Uuid) -> Option<Owner> {
+  db.user().get_user(user).await.ok().map(|user| Owner {
+    id: user.id.to_string(),
+    display_name: user.name,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use axum::{body::to_bytes, response::IntoResponse};
+
+  use super::*;
+
+  async fn render(region: &str) -> String {
+    let res = Xml(LocationConstraint::new(region.into())).into_response();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_location_constraint() {
+    assert!(
+      render("eu-central-1")
+        .await
+        .contains("<LocationConstraint>eu-central-1</LocationConstraint>")
+    );
+    let body = render("us-east-1").await;
+    assert!(!body.contains("us-east-1"));
+    assert!(body.contains("LocationConstraint"));
+  }
+}
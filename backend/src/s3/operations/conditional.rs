@@ -0,0 +1,185 @@
+use std::time::SystemTime;
+
+use axum_extra::headers::{
+  ETag, HeaderMapExt, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince,
+};
+use http::{
+  HeaderMap,
+  header::{IF_MATCH, IF_NONE_MATCH},
+};
+
+use crate::{
+  s3::{
+    error::Result,
+    interface::{ObjectInfo, WriteCondition},
+  },
+  s3_bail,
+};
+
+/// Outcome of the conditional headers of a read
+#[derive(Debug, PartialEq)]
+pub(super) enum ReadCondition {
+  Passed,
+  NotModified,
+}
+
+/// Evaluates `If-Match`, `If-Unmodified-Since`, `If-None-Match` and `If-Modified-Since`
+/// in the order given by RFC 9110, failed preconditions are rejected with 412
+pub(super) fn check_read(headers: &HeaderMap, info: &ObjectInfo) -> Result<ReadCondition> {
+  let etag = object_etag(info);
+  let last_modified = SystemTime::from(info.last_modified);
+
+  if let Some(if_match) = headers.typed_get::<IfMatch>() {
+    if !etag
+      .as_ref()
+      .is_some_and(|etag| if_match.precondition_passes(etag))
+    {
+      s3_bail!(
+        PreconditionFailed,
+        "At least one of the pre-conditions you specified did not hold"
+      );
+    }
+  } else if let Some(if_unmodified_since) = headers.typed_get::<IfUnmodifiedSince>()
+    && !if_unmodified_since.precondition_passes(last_modified)
+  {
+    s3_bail!(
+      PreconditionFailed,
+      "At least one of the pre-conditions you specified did not hold"
+    );
+  }
+
+  if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+    if etag
+      .as_ref()
+      .is_some_and(|etag| !if_none_match.precondition_passes(etag))
+    {
+      return Ok(ReadCondition::NotModified);
+    }
+  } else if let Some(if_modified_since) = headers.typed_get::<IfModifiedSince>()
+    && !if_modified_since.is_modified(last_modified)
+  {
+    return Ok(ReadCondition::NotModified);
+  }
+
+  Ok(ReadCondition::Passed)
+}
+
+/// Parses the preconditions of a write, s3 only supports `If-None-Match: *`
+/// and a single `If-Match` etag
+pub(super) fn write_condition(headers: &HeaderMap) -> Result<Option<WriteCondition>> {
+  let header = |name| {
+    headers
+      .get(name)
+      .map(|value| value.to_str().map(|value| value.trim().to_string()))
+      .transpose()
+  };
+  let (Ok(if_match), Ok(if_none_match)) = (header(IF_MATCH), header(IF_NONE_MATCH)) else {
+    s3_bail!(InvalidArgument, "Invalid conditional header");
+  };
+
+  match (if_match, if_none_match) {
+    (Some(_), Some(_)) => s3_bail!(
+      InvalidRequest,
+      "If-Match and If-None-Match can not be combined for writes"
+    ),
+    (Some(etag), None) => Ok(Some(WriteCondition::Matches(etag))),
+    (None, Some(etag)) if etag == "*" => Ok(Some(WriteCondition::NotExists)),
+    (None, Some(_)) => s3_bail!(NotImplemented, "If-None-Match only supports * for writes"),
+    (None, None) => Ok(None),
+  }
+}
+
+fn object_etag(info: &ObjectInfo) -> Option<ETag> {
+  format!("\"{}\"", info.etag).parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+  use std::time::Duration;
+
+  use axum_extra::headers::Header;
+
+  use super::*;
+  use crate::s3::interface::ObjectMetadata;
+
+  fn info() -> ObjectInfo {
+    ObjectInfo {
+      size: 4,
+      last_modified: (SystemTime::now() - Duration::from_secs(3600)).into(),
+      etag: "abc".into(),
+      metadata: ObjectMetadata::default(),
+      parts: Vec::new(),
+    }
+  }
+
+  fn check<H: Header>(header: H) -> Result<ReadCondition> {
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(header);
+    check_read(&headers, &info())
+  }
+
+  fn etag(etag: &str) -> ETag {
+    etag.parse().unwrap()
+  }
+
+  #[test]
+  fn test_check_read() {
+    let now = SystemTime::now();
+    let before = now - Duration::from_secs(7200);
+
+    assert_eq!(
+      check_read(&HeaderMap::new(), &info()).unwrap(),
+      ReadCondition::Passed
+    );
+
+    assert!(check(IfMatch::from(etag("\"abc\""))).is_ok());
+    assert!(check(IfMatch::any()).is_ok());
+    assert!(check(IfMatch::from(etag("\"other\""))).is_err());
+
+    assert!(check(IfUnmodifiedSince::from(now)).is_ok());
+    assert!(check(IfUnmodifiedSince::from(before)).is_err());
+
+    assert_eq!(
+      check(IfNoneMatch::from(etag("\"abc\""))).unwrap(),
+      ReadCondition::NotModified
+    );
+    assert_eq!(
+      check(IfNoneMatch::from(etag("\"other\""))).unwrap(),
+      ReadCondition::Passed
+    );
+
+    assert_eq!(
+      check(IfModifiedSince::from(now)).unwrap(),
+      ReadCondition::NotModified
+    );
+    assert_eq!(
+      check(IfModifiedSince::from(before)).unwrap(),
+      ReadCondition::Passed
+    );
+  }
+
+  #[test]
+  fn test_write_condition() {
+    assert_eq!(write_condition(&HeaderMap::new()).unwrap(), None);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(IF_NONE_MATCH, "*".parse().unwrap());
+    assert_eq!(
+      write_condition(&headers).unwrap(),
+      Some(WriteCondition::NotExists)
+    );
+
+    headers.insert(IF_MATCH, "\"abc\"".parse().unwrap());
+    assert!(write_condition(&headers).is_err());
+
+    headers.remove(IF_NONE_MATCH);
+    assert_eq!(
+      write_condition(&headers).unwrap(),
+      Some(WriteCondition::Matches("\"abc\"".into()))
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(IF_NONE_MATCH, "\"abc\"".parse().unwrap());
+    assert!(write_condition(&headers).is_err());
+  }
+}
@@ -0,0 +1,234 @@
+use axum::extract::{Path, Query};
+use axum_extra::headers::HeaderMapExt;
+use centaurus::{error::ErrorReportStatusExt, req::xml::Xml};
+use chrono::SecondsFormat;
+use http::{HeaderMap, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+  s3::{
+    auth::{Identity, S3Auth},
+    error::Result,
+    header::{AwzCopySource, AwzCopySourceRange, AwzMetadataDirective, AwzMetadataDirectiveHeader},
+    interface::{CopySource, S3Interface},
+    operations::object::object_metadata,
+  },
+  s3_bail,
+};
+
+#[instrument]
+pub(super) async fn copy_object(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<CopyObjectResult>> {
+  let source = copy_source(&headers)?;
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!(
+        "AccessKey {key_id} copying object {} from bucket {} to {key} in bucket {bucket}",
+        source.key,
+        source.bucket
+      );
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to copy object");
+    }
+  }
+
+  let directive = headers
+    .typed_get::<AwzMetadataDirectiveHeader>()
+    .map(|header| header.0)
+    .unwrap_or(AwzMetadataDirective::Copy);
+  if source.bucket == bucket && source.key == key && directive == AwzMetadataDirective::Copy {
+    s3_bail!(
+      InvalidRequest,
+      "Copying an object onto itself requires the REPLACE metadata directive"
+    );
+  }
+
+  let metadata = match directive {
+    AwzMetadataDirective::Copy => None,
+    AwzMetadataDirective::Replace => Some(object_metadata(&headers)?),
+  };
+  let info = interface
+    .copy_object(&source, &bucket, &key, metadata)
+    .await?;
+
+  Ok(Xml(CopyObjectResult {
+    etag: format!("\"{}\"", info.etag),
+    last_modified: info
+      .last_modified
+      .to_rfc3339_opts(SecondsFormat::Millis, true),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct CopyObjectResult {
+  #[serde(rename = "ETag")]
+  etag: String,
+  last_modified: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct UploadPartCopyQuery {
+  #[serde(rename = "partNumber")]
+  part_number: u16,
+  #[serde(rename = "uploadId")]
+  upload_id: String,
+}
+
+#[instrument]
+pub(super) async fn upload_part_copy(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<UploadPartCopyQuery>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<CopyPartResult>> {
+  let source = copy_source(&headers)?;
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!(
+        "AccessKey {key_id} copying object {} from bucket {} as part {} of {key} in bucket {bucket}",
+        source.key,
+        source.bucket,
+        query.part_number
+      );
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to upload part copy");
+    }
+  }
+
+  let range = headers
+    .typed_get::<AwzCopySourceRange>()
+    .map(|range| copy_range(&range))
+    .transpose()?;
+  let part = interface
+    .upload_part_copy(
+      &source,
+      range,
+      &bucket,
+      &key,
+      &query.upload_id,
+      query.part_number,
+    )
+    .await?;
+
+  Ok(Xml(CopyPartResult {
+    etag: format!("\"{}\"", part.etag),
+    last_modified: part
+      .last_modified
+      .to_rfc3339_opts(SecondsFormat::Millis, true),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct CopyPartResult {
+  #[serde(rename = "ETag")]
+  etag: String,
+  last_modified: String,
+}
+
+/// Parses `x-amz-copy-source` which has the form `[/]bucket/key[?versionId=id]`
+/// with an url encoded key
+fn copy_source(headers: &HeaderMap) -> Result<CopySource> {
+  let Some(AwzCopySource(source)) = headers.typed_get() else {
+    s3_bail!(InvalidArgument, "Missing or invalid copy source");
+  };
+
+  let (source, query) = match source.split_once('?') {
+    Some((source, query)) => (source, Some(query)),
+    None => (source.as_str(), None),
+  };
+  if let Some(query) = query {
+    // versioning is not supported, so only the null version exists
+    let version_id = query.strip_prefix("versionId=");
+    if version_id != Some("null") {
+      s3_bail!(
+        NoSuchVersion,
+        "Version {query} of the copy source not found"
+      );
+    }
+  }
+
+  let source = percent_decode_str(source)
+    .decode_utf8()
+    .status_context(StatusCode::BAD_REQUEST, "Invalid copy source encoding")?;
+  let source = source.strip_prefix('/').unwrap_or(&source);
+  let Some((bucket, key)) = source.split_once('/') else {
+    s3_bail!(
+      InvalidArgument,
+      "Copy source must contain a bucket and a key"
+    );
+  };
+  if bucket.is_empty() || key.is_empty() {
+    s3_bail!(
+      InvalidArgument,
+      "Copy source must contain a bucket and a key"
+    );
+  }
+
+  Ok(CopySource {
+    bucket: bucket.to_string(),
+    key: key.to_string(),
+  })
+}
+
+/// Parses `x-amz-copy-source-range` which has the form `bytes=first-last`
+fn copy_range(range: &str) -> Result<(u64, u64)> {
+  let parsed = range
+    .strip_prefix("bytes=")
+    .and_then(|range| range.split_once('-'))
+    .and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)));
+  let Some(parsed) = parsed else {
+    s3_bail!(InvalidArgument, "Invalid copy source range {range}");
+  };
+
+  Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+  use axum::response::IntoResponse;
+
+  use super::*;
+
+  #[test]
+  fn test_copy_source() {
+    for (raw, bucket, key) in [
+      ("bucket/key", "bucket", "key"),
+      ("/bucket/dir/a%20b%2Bc", "bucket", "dir/a b+c"),
+      ("/bucket/key?versionId=null", "bucket", "key"),
+    ] {
+      let mut headers = HeaderMap::new();
+      headers.insert("x-amz-copy-source", raw.parse().unwrap());
+      let source = copy_source(&headers).unwrap();
+      assert_eq!(source.bucket, bucket);
+      assert_eq!(source.key, key);
+    }
+
+    for raw in ["bucket", "/bucket/", "bucket/key?versionId=1"] {
+      let mut headers = HeaderMap::new();
+      headers.insert("x-amz-copy-source", raw.parse().unwrap());
+      assert!(copy_source(&headers).is_err());
+    }
+  }
+
+  #[test]
+  fn test_copy_range() {
+    assert_eq!(copy_range("bytes=0-99").unwrap(), (0, 99));
+    assert!(copy_range("bytes=-99").is_err());
+    assert!(copy_range("0-99").is_err());
+
+    // malformed numbers are an invalid argument, not an internal error
+    let res = copy_range("bytes=a-5").unwrap_err().into_response();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  }
+}
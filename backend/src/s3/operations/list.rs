@@ -0,0 +1,387 @@
+use axum::extract::{Path, Query};
+use base64::prelude::*;
+use centaurus::{error::ErrorReportStatusExt, req::xml::Xml};
+use chrono::SecondsFormat;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use url::form_urlencoded::byte_serialize;
+
+use crate::{
+  db::Connection,
+  s3::{
+    auth::{Identity, S3Auth},
+    error::Result,
+    interface::{ListOptions, ListPage, S3Interface},
+    operations::bucket::{Owner, owner},
+  },
+  s3_bail,
+};
+
+const MAX_KEYS: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(super) struct ListObjectsV2Query {
+  continuation_token: Option<String>,
+  delimiter: Option<String>,
+  encoding_type: Option<String>,
+  fetch_owner: Option<bool>,
+  max_keys: Option<usize>,
+  prefix: Option<String>,
+  start_after: Option<String>,
+}
+
+#[instrument]
+pub(super) async fn list_objects_v2(
+  interface: S3Interface,
+  db: Connection,
+  Path(bucket): Path<String>,
+  Query(query): Query<ListObjectsV2Query>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<ListBucketResult>> {
+  match identity {
+    Identity::AccessKey(key) => {
+      tracing::info!("AccessKey {key} listing objects in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to list objects");
+    }
+  }
+
+  let encode = url_encoding(&query.encoding_type)?;
+  let start_after = if let Some(token) = &query.continuation_token {
+    let token = BASE64_URL_SAFE_NO_PAD
+      .decode(token)
+      .status_context(StatusCode::BAD_REQUEST, "Invalid continuation token")?;
+    Some(
+      String::from_utf8(token)
+        .status_context(StatusCode::BAD_REQUEST, "Invalid continuation token")?,
+    )
+  } else {
+    query.start_after.clone()
+  };
+
+  let options = ListOptions {
+    prefix: query.prefix.clone().unwrap_or_default(),
+    delimiter: query.delimiter.clone(),
+    start_after,
+    max_keys: query.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS),
+  };
+  let page = interface.list_objects(&bucket, &options).await?;
+
+  // objects have no owner of their own, they belong to the owner of their bucket
+  let owner = match query.fetch_owner {
+    Some(true) => match interface.bucket(&bucket).await?.owner {
+      Some(user) => owner(&db, user).await,
+      None => None,
+    },
+    _ => None,
+  };
+
+  let next_continuation_token = if page.is_truncated {
+    page
+      .last_key
+      .as_ref()
+      .map(|key| BASE64_URL_SAFE_NO_PAD.encode(key))
+  } else {
+    None
+  };
+
+  Ok(Xml(ListBucketResult {
+    name: bucket,
+    prefix: encode(options.prefix.clone()),
+    delimiter: query.delimiter.map(encode),
+    max_keys: options.max_keys,
+    encoding_type: query.encoding_type,
+    key_count: page.objects.len() + page.common_prefixes.len(),
+    is_truncated: page.is_truncated,
+    continuation_token: query.continuation_token,
+    next_continuation_token,
+    start_after: query.start_after.map(encode),
+    contents: contents(&page, encode, owner.as_ref()),
+    common_prefixes: common_prefixes(&page, encode),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ListBucketResult {
+  name: String,
+  prefix: String,
+  delimiter: Option<String>,
+  max_keys: usize,
+  encoding_type: Option<String>,
+  key_count: usize,
+  is_truncated: bool,
+  continuation_token: Option<String>,
+  next_continuation_token: Option<String>,
+  start_after: Option<String>,
+  contents: Vec<Object>,
+  common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(super) struct ListObjectsQuery {
+  delimiter: Option<String>,
+  encoding_type: Option<String>,
+  marker: Option<String>,
+  max_keys: Option<usize>,
+  prefix: Option<String>,
+}
+
+#[instrument]
+pub(super) async fn list_objects(
+  interface: S3Interface,
+  Path(bucket): Path<String>,
+  Query(query): Query<ListObjectsQuery>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<ListBucketResultV1>> {
+  match identity {
+    Identity::AccessKey(key) => {
+      tracing::info!("AccessKey {key} listing objects in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to list objects");
+    }
+  }
+
+  let encode = url_encoding(&query.encoding_type)?;
+  let options = ListOptions {
+    prefix: query.prefix.clone().unwrap_or_default(),
+    delimiter: query.delimiter.clone(),
+    start_after: query.marker.clone(),
+    max_keys: query.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS),
+  };
+  let page = interface.list_objects(&bucket, &options).await?;
+
+  let next_marker = if page.is_truncated {
+    page.last_key.clone().map(encode)
+  } else {
+    None
+  };
+
+  Ok(Xml(ListBucketResultV1 {
+    name: bucket,
+    prefix: encode(options.prefix.clone()),
+    marker: encode(query.marker.unwrap_or_default()),
+    next_marker,
+    delimiter: query.delimiter.map(encode),
+    max_keys: options.max_keys,
+    encoding_type: query.encoding_type,
+    is_truncated: page.is_truncated,
+    contents: contents(&page, encode, None),
+    common_prefixes: common_prefixes(&page, encode),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename = "ListBucketResult", rename_all = "PascalCase")]
+pub(super) struct ListBucketResultV1 {
+  name: String,
+  prefix: String,
+  marker: String,
+  next_marker: Option<String>,
+  delimiter: Option<String>,
+  max_keys: usize,
+  encoding_type: Option<String>,
+  is_truncated: bool,
+  contents: Vec<Object>,
+  common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(super) struct ListObjectVersionsQuery {
+  delimiter: Option<String>,
+  encoding_type: Option<String>,
+  key_marker: Option<String>,
+  max_keys: Option<usize>,
+  prefix: Option<String>,
+  version_id_marker: Option<String>,
+}
+
+/// Versioning is not supported, so only the current version of every object is listed
+#[instrument]
+pub(super) async fn list_object_versions(
+  interface: S3Interface,
+  Path(bucket): Path<String>,
+  Query(query): Query<ListObjectVersionsQuery>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<ListVersionsResult>> {
+  match identity {
+    Identity::AccessKey(key) => {
+      tracing::info!("AccessKey {key} listing object versions in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to list object versions");
+    }
+  }
+
+  let encode = url_encoding(&query.encoding_type)?;
+  let options = ListOptions {
+    prefix: query.prefix.clone().unwrap_or_default(),
+    delimiter: query.delimiter.clone(),
+    start_after: query.key_marker.clone(),
+    max_keys: query.max_keys.unwrap_or(MAX_KEYS).min(MAX_KEYS),
+  };
+  let page = interface.list_objects(&bucket, &options).await?;
+
+  let (next_key_marker, next_version_id_marker) = if page.is_truncated {
+    (page.last_key.clone().map(encode), Some(NULL_VERSION.into()))
+  } else {
+    (None, None)
+  };
+
+  let versions = contents(&page, encode, None)
+    .into_iter()
+    .map(|object| Version {
+      key: object.key,
+      version_id: NULL_VERSION.into(),
+      is_latest: true,
+      last_modified: object.last_modified,
+      etag: object.etag,
+      size: object.size,
+      storage_class: object.storage_class,
+    })
+    .collect();
+
+  Ok(Xml(ListVersionsResult {
+    name: bucket,
+    prefix: encode(options.prefix.clone()),
+    key_marker: encode(query.key_marker.unwrap_or_default()),
+    version_id_marker: query.version_id_marker.unwrap_or_default(),
+    next_key_marker,
+    next_version_id_marker,
+    delimiter: query.delimiter.map(encode),
+    max_keys: options.max_keys,
+    encoding_type: query.encoding_type,
+    is_truncated: page.is_truncated,
+    versions,
+    common_prefixes: common_prefixes(&page, encode),
+  }))
+}
+
+/// Version id s3 reports for objects in buckets without versioning
+const NULL_VERSION: &str = "null";
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ListVersionsResult {
+  name: String,
+  prefix: String,
+  key_marker: String,
+  version_id_marker: String,
+  next_key_marker: Option<String>,
+  next_version_id_marker: Option<String>,
+  delimiter: Option<String>,
+  max_keys: usize,
+  encoding_type: Option<String>,
+  is_truncated: bool,
+  #[serde(rename = "Version")]
+  versions: Vec<Version>,
+  common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Version {
+  key: String,
+  version_id: String,
+  is_latest: bool,
+  last_modified: String,
+  #[serde(rename = "ETag")]
+  etag: String,
+  size: u64,
+  storage_class: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Object {
+  key: String,
+  last_modified: String,
+  #[serde(rename = "ETag")]
+  etag: String,
+  size: u64,
+  storage_class: String,
+  owner: Option<Owner>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct CommonPrefix {
+  pub(super) prefix: String,
+}
+
+/// Returns the function used to encode keys in the response
+pub(super) fn url_encoding(encoding_type: &Option<String>) -> Result<fn(String) -> String> {
+  match encoding_type.as_deref() {
+    None => Ok(|s| s),
+    Some("url") => Ok(|s| byte_serialize(s.as_bytes()).collect()),
+    Some(other) => s3_bail!(InvalidArgument, "Invalid encoding type {other}"),
+  }
+}
+
+fn contents(page: &ListPage, encode: fn(String) -> String, owner: Option<&Owner>) -> Vec<Object> {
+  page
+    .objects
+    .iter()
+    .map(|object| Object {
+      key: encode(object.key.clone()),
+      last_modified: object
+        .info
+        .last_modified
+        .to_rfc3339_opts(SecondsFormat::Millis, true),
+      etag: format!("\"{}\"", object.info.etag),
+      size: object.info.size,
+      storage_class: "STANDARD".into(),
+      owner: owner.cloned(),
+    })
+    .collect()
+}
+
+fn common_prefixes(page: &ListPage, encode: fn(String) -> String) -> Vec<CommonPrefix> {
+  page
+    .common_prefixes
+    .iter()
+    .map(|prefix| CommonPrefix {
+      prefix: encode(prefix.clone()),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_url_encoding() {
+    let encode = url_encoding(&Some("url".into())).unwrap();
+    assert_eq!(encode("a b+c/d".into()), "a+b%2Bc%2Fd");
+
+    let encode = url_encoding(&None).unwrap();
+    assert_eq!(encode("a b+c/d".into()), "a b+c/d");
+
+    assert!(url_encoding(&Some("base64".into())).is_err());
+  }
+
+  #[test]
+  fn test_list_bucket_result_v1_root() {
+    let result = Xml(ListBucketResultV1 {
+      name: "bucket".into(),
+      prefix: String::new(),
+      marker: String::new(),
+      next_marker: None,
+      delimiter: None,
+      max_keys: MAX_KEYS,
+      encoding_type: None,
+      is_truncated: false,
+      contents: vec![],
+      common_prefixes: vec![],
+    });
+    let xml = String::from_utf8(result.to_slice().unwrap()).unwrap();
+    assert!(xml.contains("<ListBucketResult><Name>bucket</Name>"));
+  }
+}
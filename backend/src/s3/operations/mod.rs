@@ -1,7 +1,100 @@
-use axum::Router;
+use std::collections::HashMap;
 
+use axum::{
+  Router,
+  extract::{Query, Request},
+  handler::Handler,
+  response::{IntoResponse, Response},
+  routing::get,
+};
+use tracing::instrument;
+
+use crate::s3::{
+  error::{S3Error, S3ErrorCode, S3ErrorResponse},
+  header::AWZ_COPY_SOURCE,
+};
+
+mod attributes;
 mod bucket;
+mod conditional;
+mod copy;
+mod list;
+mod multipart;
+mod object;
 
 pub fn router() -> Router {
   bucket::router()
+    .route("/{bucket}", get(get_bucket).post(object::post_object))
+    .route("/{bucket}/", get(get_bucket).post(object::post_object))
+    .route(
+      "/{bucket}/{*key}",
+      get(get_object)
+        .head(object::head_object)
+        .put(put_object)
+        .post(post_object)
+        .delete(delete_object),
+    )
+}
+
+/// S3 distinguishes most bucket operations by their query parameters
+/// so requests are dispatched to the matching handler here
+#[instrument(skip(req))]
+async fn get_bucket(Query(query): Query<HashMap<String, String>>, req: Request) -> Response {
+  if query.contains_key("location") {
+    bucket::get_bucket_location.call(req, ()).await
+  } else if query.contains_key("uploads") {
+    multipart::list_multipart_uploads.call(req, ()).await
+  } else if query.contains_key("versions") {
+    list::list_object_versions.call(req, ()).await
+  } else if query.get("list-type").is_some_and(|t| t == "2") {
+    list::list_objects_v2.call(req, ()).await
+  } else {
+    list::list_objects.call(req, ()).await
+  }
+}
+
+#[instrument(skip(req))]
+async fn get_object(Query(query): Query<HashMap<String, String>>, req: Request) -> Response {
+  if query.contains_key("uploadId") {
+    multipart::list_parts.call(req, ()).await
+  } else if query.contains_key("attributes") {
+    attributes::get_object_attributes.call(req, ()).await
+  } else {
+    object::get_object.call(req, ()).await
+  }
+}
+
+#[instrument(skip(req))]
+async fn put_object(Query(query): Query<HashMap<String, String>>, req: Request) -> Response {
+  let copy = req.headers().contains_key(&AWZ_COPY_SOURCE);
+  match (query.contains_key("uploadId"), copy) {
+    (true, true) => copy::upload_part_copy.call(req, ()).await,
+    (true, false) => multipart::upload_part.call(req, ()).await,
+    (false, true) => copy::copy_object.call(req, ()).await,
+    (false, false) => object::put_object.call(req, ()).await,
+  }
+}
+
+#[instrument(skip(req))]
+async fn post_object(Query(query): Query<HashMap<String, String>>, req: Request) -> Response {
+  if query.contains_key("uploads") {
+    multipart::create_multipart_upload.call(req, ()).await
+  } else if query.contains_key("uploadId") {
+    multipart::complete_multipart_upload.call(req, ()).await
+  } else {
+    S3ErrorResponse::from(S3Error::new(
+      S3ErrorCode::MethodNotAllowed,
+      "The specified method is not allowed against this resource",
+    ))
+    .into_response()
+  }
+}
+
+#[instrument(skip(req))]
+async fn delete_object(Query(query): Query<HashMap<String, String>>, req: Request) -> Response {
+  if query.contains_key("uploadId") {
+    multipart::abort_multipart_upload.call(req, ()).await
+  } else {
+    object::delete_object.call(req, ()).await
+  }
 }
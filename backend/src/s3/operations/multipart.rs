@@ -0,0 +1,405 @@
+use axum::extract::{Path, Query};
+use centaurus::req::xml::Xml;
+use chrono::SecondsFormat;
+use http::{HeaderMap, StatusCode, header::ETAG};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::s3::{
+  auth::{Identity, S3Auth, stream::BodyStream},
+  error::Result,
+  interface::{ListOptions, ObjectReader, S3Interface},
+  operations::{
+    conditional::write_condition,
+    list::{CommonPrefix, url_encoding},
+    object::{insert_checksum, object_metadata},
+  },
+};
+
+const MAX_PARTS: usize = 1000;
+const MAX_UPLOADS: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+pub(super) struct UploadIdQuery {
+  #[serde(rename = "uploadId")]
+  upload_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct UploadPartQuery {
+  #[serde(rename = "partNumber")]
+  part_number: u16,
+  #[serde(rename = "uploadId")]
+  upload_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(super) struct ListPartsQuery {
+  #[serde(rename = "uploadId")]
+  upload_id: String,
+  max_parts: Option<usize>,
+  part_number_marker: Option<u16>,
+}
+
+#[instrument]
+pub(super) async fn create_multipart_upload(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<InitiateMultipartUploadResult>> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} creating multipart upload for {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to create multipart upload");
+    }
+  }
+
+  let upload_id = interface
+    .create_multipart_upload(&bucket, &key, object_metadata(&headers)?)
+    .await?;
+
+  Ok(Xml(InitiateMultipartUploadResult {
+    bucket,
+    key,
+    upload_id,
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct InitiateMultipartUploadResult {
+  bucket: String,
+  key: String,
+  upload_id: String,
+}
+
+#[instrument]
+pub(super) async fn upload_part(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<UploadPartQuery>,
+  S3Auth {
+    identity,
+    mut body,
+    checksum,
+    ..
+  }: S3Auth<BodyStream>,
+) -> Result<HeaderMap> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!(
+        "AccessKey {key_id} uploading part {} of {key} in bucket {bucket}",
+        query.part_number
+      );
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to upload part");
+    }
+  }
+
+  let etag = interface
+    .upload_part(
+      &bucket,
+      &key,
+      &query.upload_id,
+      query.part_number,
+      &mut body,
+    )
+    .await?;
+
+  let mut headers = HeaderMap::new();
+  headers.insert(ETAG, format!("\"{etag}\"").parse()?);
+  insert_checksum(&mut headers, checksum.or(body.checksum()).as_ref())?;
+
+  Ok(headers)
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct CompleteMultipartUpload {
+  #[serde(rename = "Part", default)]
+  parts: Vec<CompletedPart>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct CompletedPart {
+  part_number: u16,
+  #[serde(rename = "ETag")]
+  etag: String,
+}
+
+#[instrument]
+pub(super) async fn complete_multipart_upload(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<UploadIdQuery>,
+  headers: HeaderMap,
+  S3Auth { identity, body, .. }: S3Auth<Xml<CompleteMultipartUpload>>,
+) -> Result<Xml<CompleteMultipartUploadResult>> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} completing multipart upload of {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to complete multipart upload");
+    }
+  }
+
+  let condition = write_condition(&headers)?;
+  let parts: Vec<_> = body
+    .0
+    .parts
+    .into_iter()
+    .map(|part| (part.part_number, part.etag))
+    .collect();
+  let info = interface
+    .complete_multipart_upload(&bucket, &key, &query.upload_id, &parts, condition.as_ref())
+    .await?;
+
+  Ok(Xml(CompleteMultipartUploadResult {
+    location: format!("/{bucket}/{key}"),
+    bucket,
+    key,
+    etag: format!("\"{}\"", info.etag),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct CompleteMultipartUploadResult {
+  location: String,
+  bucket: String,
+  key: String,
+  #[serde(rename = "ETag")]
+  etag: String,
+}
+
+#[instrument]
+pub(super) async fn abort_multipart_upload(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<UploadIdQuery>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<StatusCode> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} aborting multipart upload of {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to abort multipart upload");
+    }
+  }
+
+  interface
+    .abort_multipart_upload(&bucket, &key, &query.upload_id)
+    .await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[instrument]
+pub(super) async fn list_parts(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<ListPartsQuery>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<ListPartsResult>> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} listing parts of {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to list parts");
+    }
+  }
+
+  let max_parts = query.max_parts.unwrap_or(MAX_PARTS).min(MAX_PARTS);
+  let marker = query.part_number_marker.unwrap_or_default();
+  let mut parts: Vec<_> = interface
+    .list_parts(&bucket, &key, &query.upload_id)
+    .await?
+    .into_iter()
+    .filter(|part| part.part_number > marker)
+    .collect();
+
+  let is_truncated = parts.len() > max_parts;
+  parts.truncate(max_parts);
+  let next_part_number_marker = if is_truncated {
+    parts.last().map(|part| part.part_number)
+  } else {
+    None
+  };
+
+  Ok(Xml(ListPartsResult {
+    bucket,
+    key,
+    upload_id: query.upload_id,
+    part_number_marker: marker,
+    next_part_number_marker,
+    max_parts,
+    is_truncated,
+    parts: parts
+      .into_iter()
+      .map(|part| Part {
+        part_number: part.part_number,
+        last_modified: part
+          .last_modified
+          .to_rfc3339_opts(SecondsFormat::Millis, true),
+        etag: format!("\"{}\"", part.etag),
+        size: part.size,
+      })
+      .collect(),
+    storage_class: "STANDARD".into(),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ListPartsResult {
+  bucket: String,
+  key: String,
+  upload_id: String,
+  part_number_marker: u16,
+  next_part_number_marker: Option<u16>,
+  max_parts: usize,
+  is_truncated: bool,
+  #[serde(rename = "Part")]
+  parts: Vec<Part>,
+  storage_class: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Part {
+  part_number: u16,
+  last_modified: String,
+  #[serde(rename = "ETag")]
+  etag: String,
+  size: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(super) struct ListMultipartUploadsQuery {
+  delimiter: Option<String>,
+  encoding_type: Option<String>,
+  key_marker: Option<String>,
+  max_uploads: Option<usize>,
+  prefix: Option<String>,
+  upload_id_marker: Option<String>,
+}
+
+#[instrument]
+pub(super) async fn list_multipart_uploads(
+  interface: S3Interface,
+  Path(bucket): Path<String>,
+  Query(query): Query<ListMultipartUploadsQuery>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<Xml<ListMultipartUploadsResult>> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} listing multipart uploads in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to list multipart uploads");
+    }
+  }
+
+  let encode = url_encoding(&query.encoding_type)?;
+  let options = ListOptions {
+    prefix: query.prefix.clone().unwrap_or_default(),
+    delimiter: query.delimiter.clone(),
+    start_after: query.key_marker.clone(),
+    max_keys: query.max_uploads.unwrap_or(MAX_UPLOADS).min(MAX_UPLOADS),
+  };
+  let page = interface
+    .list_multipart_uploads(&bucket, &options, query.upload_id_marker.as_deref())
+    .await?;
+
+  let (next_key_marker, next_upload_id_marker) = if page.is_truncated {
+    (page.next_key_marker.map(encode), page.next_upload_id_marker)
+  } else {
+    (None, None)
+  };
+
+  Ok(Xml(ListMultipartUploadsResult {
+    bucket,
+    key_marker: encode(query.key_marker.unwrap_or_default()),
+    upload_id_marker: query.upload_id_marker.unwrap_or_default(),
+    next_key_marker,
+    next_upload_id_marker,
+    prefix: encode(options.prefix.clone()),
+    delimiter: query.delimiter.map(encode),
+    max_uploads: options.max_keys,
+    encoding_type: query.encoding_type,
+    is_truncated: page.is_truncated,
+    uploads: page
+      .uploads
+      .into_iter()
+      .map(|upload| MultipartUpload {
+        key: encode(upload.info.key),
+        upload_id: upload.upload_id,
+        storage_class: "STANDARD".into(),
+        initiated: upload
+          .info
+          .initiated
+          .to_rfc3339_opts(SecondsFormat::Millis, true),
+      })
+      .collect(),
+    common_prefixes: page
+      .common_prefixes
+      .into_iter()
+      .map(|prefix| CommonPrefix {
+        prefix: encode(prefix),
+      })
+      .collect(),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct ListMultipartUploadsResult {
+  bucket: String,
+  key_marker: String,
+  upload_id_marker: String,
+  next_key_marker: Option<String>,
+  next_upload_id_marker: Option<String>,
+  prefix: String,
+  delimiter: Option<String>,
+  max_uploads: usize,
+  encoding_type: Option<String>,
+  is_truncated: bool,
+  #[serde(rename = "Upload")]
+  uploads: Vec<MultipartUpload>,
+  common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct MultipartUpload {
+  key: String,
+  upload_id: String,
+  storage_class: String,
+  initiated: String,
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_complete_multipart_upload_body() {
+    let xml = br#"<CompleteMultipartUpload xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+      <Part><ETag>"a"</ETag><PartNumber>1</PartNumber></Part>
+      <Part><ETag>"b"</ETag><PartNumber>2</PartNumber></Part>
+    </CompleteMultipartUpload>"#;
+    let body: CompleteMultipartUpload = Xml::from_slice(xml).unwrap().0;
+    assert_eq!(body.parts.len(), 2);
+    assert_eq!(body.parts[1].part_number, 2);
+    assert_eq!(body.parts[1].etag, "\"b\"");
+  }
+}
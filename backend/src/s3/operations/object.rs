@@ -0,0 +1,533 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use axum::{
+  body::Body,
+  extract::{Path, Query},
+  response::{IntoResponse, Response},
+};
+use axum_extra::headers::{AcceptRanges, ContentLength, ContentRange, HeaderMapExt, LastModified};
+use centaurus::{error::ErrorReportStatusExt, req::xml::Xml};
+use http::{
+  HeaderMap, HeaderName, HeaderValue, StatusCode,
+  header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, LOCATION, RANGE},
+};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+use tracing::instrument;
+use url::Url;
+
+use crate::{
+  s3::{
+    auth::{Identity, S3Auth, body::TmpFile, stream::BodyStream},
+    checksum::Checksum,
+    error::Result,
+    header::AwzMpPartsCount,
+    interface::{ObjectInfo, ObjectMetadata, S3Interface},
+    operations::conditional::{ReadCondition, check_read, write_condition},
+  },
+  s3_bail,
+};
+
+/// Content type s3 reports for objects uploaded without one
+const DEFAULT_CONTENT_TYPE: &str = "binary/octet-stream";
+/// Prefix of headers containing user defined metadata
+const META_PREFIX: &str = "x-amz-meta-";
+/// Characters of a key that are escaped in the location of a posted object
+const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'/')
+  .remove(b'-')
+  .remove(b'_')
+  .remove(b'.')
+  .remove(b'~');
+
+#[instrument]
+pub(super) async fn put_object(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  headers: HeaderMap,
+  S3Auth {
+    identity,
+    mut body,
+    checksum,
+    ..
+  }: S3Auth<BodyStream>,
+) -> Result<HeaderMap> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} putting object {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to put object");
+    }
+  }
+
+  let condition = write_condition(&headers)?;
+  let metadata = ObjectMetadata {
+    checksum,
+    ..object_metadata(&headers)?
+  };
+  let info = interface
+    .put_object(&bucket, &key, metadata, &mut body, condition.as_ref())
+    .await?;
+
+  let mut headers = HeaderMap::new();
+  headers.insert(ETAG, format!("\"{}\"", info.etag).parse()?);
+  insert_checksum(&mut headers, info.metadata.checksum.as_ref())?;
+
+  Ok(headers)
+}
+
+/// Browser based upload of a form, the policy of the form was already enforced by [`S3Auth`]
+#[instrument]
+pub(super) async fn post_object(
+  interface: S3Interface,
+  Path(bucket): Path<String>,
+  S3Auth {
+    identity,
+    body,
+    additional,
+    ..
+  }: S3Auth<TmpFile>,
+) -> Result<Response> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} posting object in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to post object");
+    }
+  }
+
+  let Some(fields) = additional else {
+    s3_bail!(
+      InvalidRequest,
+      "Bucket POST must be of the enclosure-type multipart/form-data"
+    );
+  };
+  let Some(key) = fields.get("key") else {
+    s3_bail!(
+      InvalidArgument,
+      "Bucket POST must contain a field named 'key'"
+    );
+  };
+
+  // form fields carry the same metadata as the headers of a PUT
+  let form_headers: HeaderMap = fields
+    .iter()
+    .filter_map(|(name, value)| {
+      Some((
+        HeaderName::try_from(name).ok()?,
+        HeaderValue::try_from(value).ok()?,
+      ))
+    })
+    .collect();
+  let mut file = File::open(&body.0).await?;
+  let info = interface
+    .put_object(
+      &bucket,
+      key,
+      object_metadata(&form_headers)?,
+      &mut file,
+      None,
+    )
+    .await?;
+
+  let etag = format!("\"{}\"", info.etag);
+  let location = format!("/{bucket}/{}", utf8_percent_encode(key, KEY_ENCODE));
+  let mut headers = HeaderMap::new();
+  headers.insert(ETAG, etag.parse()?);
+  headers.insert(LOCATION, location.parse()?);
+
+  if let Some(redirect) = fields
+    .get("success_action_redirect")
+    .or(fields.get("redirect"))
+    && let Ok(mut url) = Url::parse(redirect)
+  {
+    url
+      .query_pairs_mut()
+      .append_pair("bucket", &bucket)
+      .append_pair("key", key)
+      .append_pair("etag", &etag);
+    headers.insert(LOCATION, url.as_str().parse()?);
+    return Ok((StatusCode::SEE_OTHER, headers).into_response());
+  }
+
+  Ok(
+    match fields.get("success_action_status").map(String::as_str) {
+      Some("200") => (StatusCode::OK, headers).into_response(),
+      Some("201") => (
+        StatusCode::CREATED,
+        headers,
+        Xml(PostResponse {
+          location,
+          bucket,
+          key: key.clone(),
+          etag,
+        }),
+      )
+        .into_response(),
+      _ => (StatusCode::NO_CONTENT, headers).into_response(),
+    },
+  )
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(super) struct PostResponse {
+  location: String,
+  bucket: String,
+  key: String,
+  #[serde(rename = "ETag")]
+  etag: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ReadQuery {
+  part_number: Option<u16>,
+}
+
+#[instrument(skip(headers))]
+pub(super) async fn get_object(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<ReadQuery>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<(StatusCode, HeaderMap, Body)> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} getting object {key} from bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to get object");
+    }
+  }
+
+  // an overwrite between reading the metadata and opening the object would
+  // send headers of one write with the body of another
+  let _lock = interface.lock_object(&bucket, &key).await;
+  let info = interface.head_object(&bucket, &key).await?;
+  let (status, headers, range) = match read_object(&headers, &query, &info)? {
+    ObjectRead::NotModified(headers) => {
+      return Ok((StatusCode::NOT_MODIFIED, headers, Body::empty()));
+    }
+    ObjectRead::Content {
+      status,
+      headers,
+      range,
+    } => (status, headers, range),
+  };
+  let reader = interface.read_object(&bucket, &key, range).await?;
+
+  Ok((
+    status,
+    headers,
+    Body::from_stream(ReaderStream::new(reader)),
+  ))
+}
+
+#[instrument(skip(headers))]
+pub(super) async fn head_object(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  Query(query): Query<ReadQuery>,
+  headers: HeaderMap,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<(StatusCode, HeaderMap)> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} heading object {key} in bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to head object");
+    }
+  }
+
+  let info = interface.head_object(&bucket, &key).await?;
+
+  Ok(match read_object(&headers, &query, &info)? {
+    ObjectRead::NotModified(headers) => (StatusCode::NOT_MODIFIED, headers),
+    ObjectRead::Content {
+      status, headers, ..
+    } => (status, headers),
+  })
+}
+
+#[derive(Debug)]
+enum ObjectRead {
+  NotModified(HeaderMap),
+  Content {
+    status: StatusCode,
+    headers: HeaderMap,
+    /// first and last byte to read, `None` for the whole object
+    range: Option<(u64, u64)>,
+  },
+}
+
+/// Decides what a GET or HEAD returns based on the conditional headers,
+/// the `Range` header and the `partNumber` query
+fn read_object(headers: &HeaderMap, query: &ReadQuery, info: &ObjectInfo) -> Result<ObjectRead> {
+  if check_read(headers, info)? == ReadCondition::NotModified {
+    let mut headers = HeaderMap::new();
+    headers.insert(ETAG, format!("\"{}\"", info.etag).parse()?);
+    headers.typed_insert(LastModified::from(SystemTime::from(info.last_modified)));
+    return Ok(ObjectRead::NotModified(headers));
+  }
+
+  let range = match (query.part_number, headers.get(RANGE)) {
+    (Some(_), Some(_)) => s3_bail!(
+      InvalidRequest,
+      "Cannot specify both Range header and partNumber query parameter"
+    ),
+    (Some(part_number), None) => Some(part_range(info, part_number)?),
+    (None, Some(range)) => byte_range(range, info.size)?,
+    (None, None) => None,
+  };
+
+  let mut object_headers = object_headers(info)?;
+  object_headers.typed_insert(AcceptRanges::bytes());
+  if query.part_number.is_some() && !info.parts.is_empty() {
+    object_headers.typed_insert(AwzMpPartsCount(info.parts.len()));
+  }
+
+  let Some((first, last)) = range else {
+    // the stored checksum only matches reads of the whole object
+    insert_checksum(&mut object_headers, info.metadata.checksum.as_ref())?;
+    return Ok(ObjectRead::Content {
+      status: StatusCode::OK,
+      headers: object_headers,
+      range: None,
+    });
+  };
+  object_headers.typed_insert(ContentLength(last - first + 1));
+  object_headers.typed_insert(
+    ContentRange::bytes(first..=last, info.size).status(StatusCode::INTERNAL_SERVER_ERROR)?,
+  );
+
+  Ok(ObjectRead::Content {
+    status: StatusCode::PARTIAL_CONTENT,
+    headers: object_headers,
+    range: Some((first, last)),
+  })
+}
+
+/// Bytes of a part of a multipart object, other objects consist of a single part
+fn part_range(info: &ObjectInfo, part_number: u16) -> Result<(u64, u64)> {
+  let parts = if info.parts.is_empty() {
+    vec![info.size]
+  } else {
+    info.parts.clone()
+  };
+  let index = usize::from(part_number);
+  if index == 0 || index > parts.len() || parts[index - 1] == 0 {
+    s3_bail!(
+      InvalidPartNumber,
+      "The requested partnumber {part_number} is not satisfiable"
+    );
+  }
+
+  let first: u64 = parts[..index - 1].iter().sum();
+  Ok((first, first + parts[index - 1] - 1))
+}
+
+/// Parses a single range of the `Range` header, unsupported or malformed ranges are ignored
+fn byte_range(range: &HeaderValue, size: u64) -> Result<Option<(u64, u64)>> {
+  let Some((first, last)) = range
+    .to_str()
+    .ok()
+    .and_then(|range| range.trim().strip_prefix("bytes="))
+    .filter(|range| !range.contains(','))
+    .and_then(|range| range.split_once('-'))
+  else {
+    return Ok(None);
+  };
+
+  let range = match (first.trim(), last.trim()) {
+    ("", "") => return Ok(None),
+    ("", suffix) => match suffix.parse::<u64>() {
+      Ok(0) => None,
+      Ok(suffix) if size > 0 => Some((size.saturating_sub(suffix), size - 1)),
+      Ok(_) => None,
+      Err(_) => return Ok(None),
+    },
+    (first, last) => {
+      let Ok(first) = first.parse::<u64>() else {
+        return Ok(None);
+      };
+      let last = match last {
+        "" => u64::MAX,
+        last => match last.parse::<u64>() {
+          Ok(last) if last >= first => last,
+          _ => return Ok(None),
+        },
+      };
+      (first < size).then(|| (first, last.min(size - 1)))
+    }
+  };
+
+  match range {
+    Some(range) => Ok(Some(range)),
+    None => s3_bail!(
+      InvalidRange,
+      "The requested range is not satisfiable for an object of {size} bytes"
+    ),
+  }
+}
+
+#[instrument]
+pub(super) async fn delete_object(
+  interface: S3Interface,
+  Path((bucket, key)): Path<(String, String)>,
+  S3Auth { identity, .. }: S3Auth,
+) -> Result<StatusCode> {
+  match identity {
+    Identity::AccessKey(key_id) => {
+      tracing::info!("AccessKey {key_id} deleting object {key} from bucket {bucket}");
+    }
+    Identity::Anonymous => {
+      tracing::warn!("Anonymous access to delete object");
+    }
+  }
+
+  interface.delete_object(&bucket, &key).await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+fn object_headers(info: &ObjectInfo) -> Result<HeaderMap> {
+  let metadata = &info.metadata;
+  let mut headers = HeaderMap::new();
+  headers.insert(ETAG, format!("\"{}\"", info.etag).parse()?);
+  headers.insert(
+    CONTENT_TYPE,
+    match &metadata.content_type {
+      Some(content_type) => content_type.parse()?,
+      None => HeaderValue::from_static(DEFAULT_CONTENT_TYPE),
+    },
+  );
+  if let Some(cache_control) = &metadata.cache_control {
+    headers.insert(CACHE_CONTROL, cache_control.parse()?);
+  }
+  if let Some(content_disposition) = &metadata.content_disposition {
+    headers.insert(CONTENT_DISPOSITION, content_disposition.parse()?);
+  }
+  for (name, value) in &metadata.user {
+    headers.insert(
+      format!("{META_PREFIX}{name}")
+        .parse::<HeaderName>()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)?,
+      value.parse()?,
+    );
+  }
+  headers.typed_insert(ContentLength(info.size));
+  headers.typed_insert(LastModified::from(SystemTime::from(info.last_modified)));
+
+  Ok(headers)
+}
+
+pub(super) fn insert_checksum(headers: &mut HeaderMap, checksum: Option<&Checksum>) -> Result<()> {
+  if let Some(checksum) = checksum {
+    headers.insert(checksum.algorithm.header(), checksum.value.parse()?);
+  }
+  Ok(())
+}
+
+/// Collects the metadata sent along with a request that creates an object
+pub(super) fn object_metadata(headers: &HeaderMap) -> Result<ObjectMetadata> {
+  let header = |name: HeaderName| headers.get(name).map(header_string).transpose();
+
+  let mut user = BTreeMap::new();
+  for (name, value) in headers {
+    if let Some(name) = name.as_str().strip_prefix(META_PREFIX) {
+      user.insert(name.to_string(), header_string(value)?);
+    }
+  }
+
+  Ok(ObjectMetadata {
+    content_type: header(CONTENT_TYPE)?,
+    cache_control: header(CACHE_CONTROL)?,
+    content_disposition: header(CONTENT_DISPOSITION)?,
+    user,
+    checksum: None,
+  })
+}
+
+fn header_string(value: &HeaderValue) -> Result<String> {
+  Ok(
+    value
+      .to_str()
+      .status_context(StatusCode::BAD_REQUEST, "Invalid header value")?
+      .to_string(),
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_object_metadata_roundtrip() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers.insert("x-amz-meta-color", HeaderValue::from_static("blue"));
+    headers.insert("x-amz-date", HeaderValue::from_static("20250101T000000Z"));
+
+    let metadata = object_metadata(&headers).unwrap();
+    assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(metadata.cache_control.as_deref(), Some("no-cache"));
+    assert_eq!(metadata.content_disposition, None);
+    assert_eq!(metadata.user.len(), 1);
+
+    let info = ObjectInfo {
+      size: 4,
+      last_modified: chrono::Utc::now(),
+      etag: "etag".into(),
+      metadata,
+      parts: Vec::new(),
+    };
+    let headers = object_headers(&info).unwrap();
+    assert_eq!(headers[CONTENT_TYPE], "text/plain");
+    assert_eq!(headers["x-amz-meta-color"], "blue");
+    assert!(!headers.contains_key(CONTENT_DISPOSITION));
+  }
+
+  #[test]
+  fn test_byte_range() {
+    let range = |value: &'static str| byte_range(&HeaderValue::from_static(value), 10);
+
+    assert_eq!(range("bytes=0-4").unwrap(), Some((0, 4)));
+    assert_eq!(range("bytes=5-").unwrap(), Some((5, 9)));
+    assert_eq!(range("bytes=-3").unwrap(), Some((7, 9)));
+    assert_eq!(range("bytes=-20").unwrap(), Some((0, 9)));
+    assert_eq!(range("bytes=8-100").unwrap(), Some((8, 9)));
+    // multiple or malformed ranges are served as the whole object
+    assert_eq!(range("bytes=0-1,3-4").unwrap(), None);
+    assert_eq!(range("bytes=4-2").unwrap(), None);
+    assert_eq!(range("items=0-4").unwrap(), None);
+    assert!(range("bytes=10-").is_err());
+    assert!(range("bytes=-0").is_err());
+  }
+
+  #[test]
+  fn test_part_range() {
+    let mut info = ObjectInfo {
+      size: 12,
+      last_modified: chrono::Utc::now(),
+      etag: "etag-3".into(),
+      metadata: ObjectMetadata::default(),
+      parts: vec![5, 5, 2],
+    };
+    assert_eq!(part_range(&info, 1).unwrap(), (0, 4));
+    assert_eq!(part_range(&info, 3).unwrap(), (10, 11));
+    assert!(part_range(&info, 0).is_err());
+    assert!(part_range(&info, 4).is_err());
+
+    info.parts.clear();
+    assert_eq!(part_range(&info, 1).unwrap(), (0, 11));
+    assert!(part_range(&info, 2).is_err());
+  }
+}
@@ -0,0 +1,93 @@
+use centaurus::{FromReqExtension, error::Result};
+use tracing::instrument;
+
+use crate::{
+  config::EnvConfig,
+  s3::{
+    auth::credential::AWS4Credential,
+    error::{S3Error, S3ErrorCode},
+  },
+};
+
+/// Regions requests may be signed for and buckets may be created in
+#[derive(FromReqExtension, Clone, Debug)]
+pub struct Regions {
+  pub default: String,
+  allowed: Vec<String>,
+}
+
+impl Regions {
+  pub fn new(config: &EnvConfig) -> Self {
+    let mut allowed = config.s3_allowed_regions.clone();
+    if !allowed.contains(&config.s3_region) {
+      allowed.push(config.s3_region.clone());
+    }
+
+    Self {
+      default: config.s3_region.clone(),
+      allowed,
+    }
+  }
+
+  pub fn is_allowed(&self, region: &str) -> bool {
+    self.allowed.iter().any(|allowed| allowed == region)
+  }
+
+  /// Rejects credentials scoped to a region that is not served here
+  #[instrument]
+  pub fn check(&self, credential: &AWS4Credential) -> Result<()> {
+    if !self.is_allowed(&credential.region) {
+      return Err(
+        S3Error::new(
+          S3ErrorCode::AuthorizationHeaderMalformed,
+          format!(
+            "The authorization header is malformed; the region '{}' is wrong; expecting '{}'",
+            credential.region, self.default
+          ),
+        )
+        .with_region(&self.default)
+        .into(),
+      );
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+pub mod test {
+  use super::*;
+
+  pub fn test_regions() -> Regions {
+    Regions::new(&EnvConfig::default())
+  }
+
+  fn credential(region: &str) -> AWS4Credential {
+    AWS4Credential {
+      access_key: "test".into(),
+      date: "20251020".into(),
+      region: region.into(),
+    }
+  }
+
+  #[test]
+  fn test_check() {
+    let config = EnvConfig {
+      s3_region: "eu-central-1".into(),
+      s3_allowed_regions: vec!["us-east-1".into()],
+      ..Default::default()
+    };
+    let regions = Regions::new(&config);
+
+    assert_eq!(regions.default, "eu-central-1");
+    assert!(regions.is_allowed("eu-central-1"));
+    assert!(regions.is_allowed("us-east-1"));
+    assert!(!regions.is_allowed("us-west-2"));
+
+    assert!(regions.check(&credential("us-east-1")).is_ok());
+    let error = regions.check(&credential("us-west-2")).unwrap_err();
+    let error = error.error.downcast_ref::<S3Error>().unwrap();
+    assert_eq!(error.code, S3ErrorCode::AuthorizationHeaderMalformed);
+    assert_eq!(error.region.as_deref(), Some("eu-central-1"));
+  }
+}
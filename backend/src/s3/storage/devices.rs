@@ -0,0 +1,195 @@
+use std::{
+  collections::BTreeMap,
+  io::{Error, ErrorKind, Result},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use futures::future::{BoxFuture, join_all};
+
+use crate::s3::storage::{DirEntry, ScrubReport, Storage, no_raid::NoRaid};
+
+/// Directories of a redundant storage
+///
+/// A directory that fails a write is marked degraded and skipped until the process restarts
+#[derive(Debug)]
+pub(super) struct Devices {
+  devices: Vec<Device>,
+}
+
+#[derive(Debug)]
+pub(super) struct Device {
+  pub(super) storage: NoRaid,
+  healthy: AtomicBool,
+}
+
+impl Device {
+  pub(super) fn is_healthy(&self) -> bool {
+    self.healthy.load(Ordering::Relaxed)
+  }
+}
+
+impl Devices {
+  pub(super) fn new(storages: Vec<NoRaid>) -> Self {
+    Self {
+      devices: storages
+        .into_iter()
+        .map(|storage| Device {
+          storage,
+          healthy: AtomicBool::new(true),
+        })
+        .collect(),
+    }
+  }
+
+  pub(super) fn all(&self) -> &[Device] {
+    &self.devices
+  }
+
+  pub(super) fn healthy(&self) -> impl Iterator<Item = &Device> {
+    self.devices.iter().filter(|device| device.is_healthy())
+  }
+
+  pub(super) fn mark_degraded(&self, device: &Device, error: &Error) {
+    if device.healthy.swap(false, Ordering::Relaxed) {
+      tracing::error!(
+        "Storage directory {} is degraded: {error}",
+        device.storage.base_path().display()
+      );
+    }
+  }
+
+  /// Runs a modification on all healthy directories, it succeeds if at least one succeeds
+  pub(super) async fn modify_all<'a>(
+    &'a self,
+    op: impl Fn(&'a NoRaid) -> BoxFuture<'a, Result<()>>,
+  ) -> Result<()> {
+    let devices: Vec<_> = self.healthy().collect();
+    let results = join_all(devices.iter().map(|device| op(&device.storage))).await;
+    self.settle(&devices, results, 1, true)
+  }
+
+  /// Degrades the directories that failed while others succeeded, a missing file
+  /// is only tolerated for modifications that remove it anyway
+  pub(super) fn settle(
+    &self,
+    devices: &[&Device],
+    results: Vec<Result<()>>,
+    required: usize,
+    allow_missing: bool,
+  ) -> Result<()> {
+    let succeeded = results.iter().filter(|result| result.is_ok()).count();
+    if succeeded == 0 {
+      return results
+        .into_iter()
+        .find_map(|result| result.err())
+        .map_or_else(|| Err(no_healthy_device()), Err);
+    }
+
+    for (device, result) in devices.iter().zip(results) {
+      if let Err(e) = result
+        && !(allow_missing && e.kind() == ErrorKind::NotFound)
+      {
+        self.mark_degraded(device, &e);
+      }
+    }
+
+    if succeeded < required {
+      return Err(Error::other(format!(
+        "Only {succeeded} of {required} required storage directories succeeded"
+      )));
+    }
+    Ok(())
+  }
+
+  /// Removes an empty directory from all healthy directories, a directory that is
+  /// missing or still has entries in one of them is not a failure of that directory
+  pub(super) async fn delete_empty_dir(&self, path: &Path) -> Result<()> {
+    let devices: Vec<_> = self.healthy().collect();
+    let results = join_all(
+      devices
+        .iter()
+        .map(|device| device.storage.delete_empty_dir(path)),
+    )
+    .await;
+
+    let mut not_empty = None;
+    for (device, result) in devices.iter().zip(results) {
+      match result {
+        Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => not_empty = Some(e),
+        Err(e) if e.kind() != ErrorKind::NotFound => self.mark_degraded(device, &e),
+        _ => {}
+      }
+    }
+    not_empty.map_or(Ok(()), Err)
+  }
+
+  /// Lists the union of all healthy directories so files missing in a single one are still found
+  pub(super) async fn list_entries(&self, path: &Path) -> Result<Vec<DirEntry>> {
+    let mut entries = BTreeMap::new();
+    let mut found = false;
+    let mut error = None;
+    for device in self.healthy() {
+      match device.storage.list_entries(path).await {
+        Ok(list) => {
+          found = true;
+          for entry in list {
+            entries.entry(entry.name.clone()).or_insert(entry);
+          }
+        }
+        Err(e) => error = error.or(Some(e)),
+      }
+    }
+
+    if !found {
+      return Err(error.unwrap_or_else(no_healthy_device));
+    }
+    Ok(entries.into_values().collect())
+  }
+
+  /// Removes the remains of a file that is missing from some healthy directories, they
+  /// are left over from a delete that missed a degraded directory
+  ///
+  /// Returns `None` if the file is in all or none of the directories
+  pub(super) async fn remove_orphaned(&self, path: &Path) -> Option<ScrubReport> {
+    let devices: Vec<_> = self.healthy().collect();
+    let infos = join_all(devices.iter().map(|device| device.storage.file_info(path))).await;
+    let missing: Vec<_> = infos
+      .iter()
+      .map(|info| matches!(info, Err(e) if e.kind() == ErrorKind::NotFound))
+      .collect();
+    if missing.iter().all(|missing| !missing) || missing.iter().all(|missing| *missing) {
+      return None;
+    }
+
+    let mut report = ScrubReport::default();
+    for (device, missing) in devices.iter().zip(missing) {
+      if missing {
+        continue;
+      }
+      report.damaged += 1;
+      match device.storage.delete_file(path).await {
+        Ok(()) => report.repaired += 1,
+        Err(e) => tracing::warn!(
+          "Failed to remove orphaned {} in {}: {e}",
+          path.display(),
+          device.storage.base_path().display()
+        ),
+      }
+    }
+    Some(report)
+  }
+
+  pub(super) fn degraded(&self) -> Vec<PathBuf> {
+    self
+      .devices
+      .iter()
+      .filter(|device| !device.is_healthy())
+      .map(|device| device.storage.base_path().to_path_buf())
+      .collect()
+  }
+}
+
+pub(super) fn no_healthy_device() -> Error {
+  Error::other("No healthy storage directory left")
+}
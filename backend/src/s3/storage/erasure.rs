@@ -0,0 +1,936 @@
+use std::{
+  collections::HashMap,
+  io::{Error, ErrorKind, Result},
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use axum::body::Bytes;
+use futures::{
+  SinkExt,
+  channel::mpsc,
+  future::{join, join_all},
+};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::s3::storage::{
+  DirEntry, FileInfo, ScrubReport, Storage,
+  devices::{Device, Devices, no_healthy_device},
+  no_raid::NoRaid,
+};
+
+/// Bytes every shard contributes to a stripe
+const SHARD_SIZE: usize = 64 * 1024;
+/// A shard of a stripe followed by its CRC32
+const BLOCK_SIZE: usize = SHARD_SIZE + 4;
+const MAGIC: &[u8; 4] = b"SGEC";
+const MIN_PARITY_SHARDS: usize = 2;
+/// File size, write id, CRC32 and magic
+const TRAILER_SIZE: usize = 8 + 16 + 4 + 4;
+
+type ShardReader = Box<dyn AsyncRead + Unpin + Send>;
+type ShardSender = mpsc::Sender<Result<Bytes>>;
+
+/// Stripes every file into data and parity shards with Reed-Solomon coding (RAID6 like),
+/// shard `i` of a file is stored under the same path in directory `i`
+///
+/// A shard file holds one checksummed block per stripe and ends with a trailer naming
+/// the file size and the write it belongs to, files stay readable as long as no more
+/// shards than there are parity shards are missing or corrupt
+#[derive(Debug)]
+pub struct Erasure {
+  shards: Devices,
+  codec: Arc<ReedSolomon>,
+}
+
+/// Shards of the latest complete write of a file
+struct Located {
+  size: u64,
+  write_id: Uuid,
+  info: FileInfo,
+  /// whether the shard at the index belongs to the write
+  shards: Vec<bool>,
+}
+
+impl Erasure {
+  pub fn new(shards: Vec<NoRaid>, parity_shards: usize) -> Result<Self> {
+    // with a single parity shard the write quorum is every shard, so one failed
+    // directory would block all writes
+    if parity_shards < MIN_PARITY_SHARDS {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("Erasure coding requires at least {MIN_PARITY_SHARDS} parity shards"),
+      ));
+    }
+    let data_shards = shards.len().saturating_sub(parity_shards);
+    let codec = ReedSolomon::new(data_shards, parity_shards).map_err(|e| {
+      Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+          "Invalid erasure coding with {data_shards} data and {parity_shards} parity shards: {e}"
+        ),
+      )
+    })?;
+
+    Ok(Self {
+      shards: Devices::new(shards),
+      codec: Arc::new(codec),
+    })
+  }
+
+  fn data_shards(&self) -> usize {
+    self.codec.data_shard_count()
+  }
+
+  /// Shards a write has to reach, one more than needed to restore the file so a
+  /// single failed shard does not make a new write unrecoverable, and a majority so
+  /// an older write can never keep more shards than an acknowledged newer one
+  fn write_quorum(&self) -> usize {
+    (self.data_shards() + 1).max(self.shards.all().len() / 2 + 1)
+  }
+
+  fn stripe_size(&self) -> u64 {
+    (self.data_shards() * SHARD_SIZE) as u64
+  }
+
+  /// Finds the write of a file with the most readable shards, or the latest of them
+  async fn locate(&self, path: &Path) -> Result<Located> {
+    let trailers = join_all(self.shards.all().iter().map(|device| async move {
+      if !device.is_healthy() {
+        return Err(no_healthy_device());
+      }
+      read_trailer(&device.storage, path, self.stripe_size()).await
+    }))
+    .await;
+
+    let mut writes: HashMap<Uuid, Vec<usize>> = HashMap::new();
+    let mut error = None;
+    for (index, trailer) in trailers.iter().enumerate() {
+      match trailer {
+        Ok((trailer, _)) => writes.entry(trailer.write_id).or_default().push(index),
+        Err(e) if e.kind() == ErrorKind::NotFound || error.is_none() => {
+          error = Some(Error::new(e.kind(), e.to_string()))
+        }
+        Err(_) => (),
+      }
+    }
+
+    // ties are broken by the newer write
+    let Some(indices) = writes
+      .into_iter()
+      .max_by_key(|(write_id, indices)| (indices.len(), *write_id))
+      .map(|(_, indices)| indices)
+    else {
+      return Err(error.unwrap_or_else(no_healthy_device));
+    };
+    if indices.len() < self.data_shards() {
+      return Err(Error::new(
+        ErrorKind::InvalidData,
+        format!(
+          "Only {} of {} required shards of {} are readable",
+          indices.len(),
+          self.data_shards(),
+          path.display()
+        ),
+      ));
+    }
+
+    let Ok((trailer, info)) = &trailers[indices[0]] else {
+      unreachable!("located shards have a trailer");
+    };
+    let mut shards = vec![false; trailers.len()];
+    for index in indices {
+      shards[index] = true;
+    }
+    Ok(Located {
+      size: trailer.size,
+      write_id: trailer.write_id,
+      info: FileInfo {
+        size: trailer.size,
+        modified: info.modified,
+      },
+      shards,
+    })
+  }
+
+  /// Opens a writer for every healthy shard directory whose index is selected
+  fn shard_writers<'a>(
+    &'a self,
+    path: &'a Path,
+    selected: impl Fn(usize) -> bool,
+  ) -> (
+    Vec<Option<ShardSender>>,
+    Vec<&'a Device>,
+    Vec<impl Future<Output = Result<()>> + 'a>,
+  ) {
+    let mut senders = Vec::new();
+    let mut devices = Vec::new();
+    let mut writes = Vec::new();
+    for (index, device) in self.shards.all().iter().enumerate() {
+      if !device.is_healthy() || !selected(index) {
+        senders.push(None);
+        continue;
+      }
+      let (sender, receiver) = mpsc::channel::<Result<Bytes>>(1);
+      senders.push(Some(sender));
+      devices.push(device);
+      writes.push(async move {
+        let mut reader = StreamReader::new(receiver);
+        device.storage.stream_write_file(path, &mut reader).await
+      });
+    }
+    (senders, devices, writes)
+  }
+}
+
+/// Reads the trailer of a shard file and checks that the file has the expected length
+async fn read_trailer(
+  storage: &NoRaid,
+  path: &Path,
+  stripe_size: u64,
+) -> Result<(Trailer, FileInfo)> {
+  let info = storage.file_info(path).await?;
+  let corrupt = || {
+    Error::new(
+      ErrorKind::InvalidData,
+      format!(
+        "Shard {} in {} is corrupt",
+        path.display(),
+        storage.base_path().display()
+      ),
+    )
+  };
+  if info.size < TRAILER_SIZE as u64 {
+    return Err(corrupt());
+  }
+
+  let mut buf = [0; TRAILER_SIZE];
+  storage
+    .stream_read_file_range(path, info.size - TRAILER_SIZE as u64, TRAILER_SIZE as u64)
+    .await?
+    .read_exact(&mut buf)
+    .await?;
+  let trailer = Trailer::decode(&buf).ok_or_else(corrupt)?;
+  if info.size != trailer.size.div_ceil(stripe_size) * BLOCK_SIZE as u64 + TRAILER_SIZE as u64 {
+    return Err(corrupt());
+  }
+  Ok((trailer, info))
+}
+
+struct Trailer {
+  size: u64,
+  write_id: Uuid,
+}
+
+impl Trailer {
+  fn encode(&self) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(TRAILER_SIZE);
+    buf.extend(self.size.to_le_bytes());
+    buf.extend(self.write_id.as_bytes());
+    buf.extend(crc32fast::hash(&buf).to_le_bytes());
+    buf.extend(MAGIC);
+    buf
+  }
+
+  fn decode(buf: &[u8; TRAILER_SIZE]) -> Option<Self> {
+    let (content, rest) = buf.split_at(24);
+    let (crc, magic) = rest.split_at(4);
+    if magic != MAGIC || crc32fast::hash(content).to_le_bytes() != crc {
+      return None;
+    }
+
+    let (size, write_id) = content.split_at(8);
+    Some(Self {
+      size: u64::from_le_bytes(size.try_into().ok()?),
+      write_id: Uuid::from_slice(write_id).ok()?,
+    })
+  }
+}
+
+/// Appends the CRC32 to a shard of a stripe
+fn block(mut shard: Vec<u8>) -> Bytes {
+  let crc = crc32fast::hash(&shard);
+  shard.extend(crc.to_le_bytes());
+  Bytes::from(shard)
+}
+
+fn verify_block(block: &[u8]) -> bool {
+  let (shard, crc) = block.split_at(SHARD_SIZE);
+  crc32fast::hash(shard).to_le_bytes()[..] == *crc
+}
+
+/// Checks every block of a shard against its checksum
+async fn verify_shard(storage: &NoRaid, path: &Path, stripes: u64) -> bool {
+  let Ok(mut reader) = storage
+    .stream_read_file_range(path, 0, stripes * BLOCK_SIZE as u64)
+    .await
+  else {
+    return false;
+  };
+  let mut buf = vec![0; BLOCK_SIZE];
+  for _ in 0..stripes {
+    if reader.read_exact(&mut buf).await.is_err() || !verify_block(&buf) {
+      return false;
+    }
+  }
+  true
+}
+
+/// Reads until the buffer is full or the reader ends
+async fn read_full(reader: &mut (dyn AsyncRead + Unpin + Send), buf: &mut [u8]) -> Result<usize> {
+  let mut filled = 0;
+  while filled < buf.len() {
+    match reader.read(&mut buf[filled..]).await? {
+      0 => break,
+      read => filled += read,
+    }
+  }
+  Ok(filled)
+}
+
+/// Encodes the reader stripe by stripe and sends each shard to the writer at its index
+async fn encode(
+  reader: &mut (dyn AsyncRead + Unpin + Send),
+  codec: &ReedSolomon,
+  mut senders: Vec<Option<ShardSender>>,
+) -> Result<()> {
+  let result = encode_stripes(reader, codec, &mut senders).await;
+  forward_error(&mut senders, result).await
+}
+
+/// Rebuilds all shards from the intact ones and sends the rebuilt shards to the
+/// writers at their index
+async fn rebuild(
+  path: &Path,
+  codec: &ReedSolomon,
+  mut readers: Vec<Option<ShardReader>>,
+  stripes: u64,
+  trailer: Trailer,
+  mut senders: Vec<Option<ShardSender>>,
+) -> Result<()> {
+  let result = async {
+    for _ in 0..stripes {
+      let mut shards = read_stripe(path, &mut readers).await;
+      codec.reconstruct(&mut shards).map_err(|e| {
+        Error::new(
+          ErrorKind::InvalidData,
+          format!("Failed to reconstruct {}: {e}", path.display()),
+        )
+      })?;
+      for (shard, sender) in shards.into_iter().zip(senders.iter_mut()) {
+        if let (Some(shard), Some(sender)) = (shard, sender) {
+          let _ = sender.send(Ok(block(shard))).await;
+        }
+      }
+    }
+    send_all(&mut senders, Bytes::from(trailer.encode())).await;
+    Ok(())
+  }
+  .await;
+  forward_error(&mut senders, result).await
+}
+
+async fn send_all(senders: &mut [Option<ShardSender>], data: Bytes) {
+  for sender in senders.iter_mut().flatten() {
+    // shards that failed already dropped their receiver
+    let _ = sender.send(Ok(data.clone())).await;
+  }
+}
+
+/// Forwards an error to all writers so no shard of a truncated file is committed
+async fn forward_error(senders: &mut [Option<ShardSender>], result: Result<()>) -> Result<()> {
+  if let Err(e) = &result {
+    for sender in senders.iter_mut().flatten() {
+      let _ = sender.send(Err(Error::new(e.kind(), e.to_string()))).await;
+    }
+  }
+  result
+}
+
+async fn encode_stripes(
+  reader: &mut (dyn AsyncRead + Unpin + Send),
+  codec: &ReedSolomon,
+  senders: &mut [Option<ShardSender>],
+) -> Result<()> {
+  let mut stripe = vec![0; codec.data_shard_count() * SHARD_SIZE];
+  let mut size = 0;
+  loop {
+    let filled = read_full(reader, &mut stripe).await?;
+    if filled == 0 {
+      break;
+    }
+    size += filled as u64;
+    stripe[filled..].fill(0);
+
+    let mut shards: Vec<_> = stripe.chunks(SHARD_SIZE).map(<[u8]>::to_vec).collect();
+    shards.resize(codec.total_shard_count(), vec![0; SHARD_SIZE]);
+    codec.encode(&mut shards).map_err(Error::other)?;
+
+    for (shard, sender) in shards.into_iter().zip(senders.iter_mut()) {
+      if let Some(sender) = sender {
+        // shards that failed already dropped their receiver
+        let _ = sender.send(Ok(block(shard))).await;
+      }
+    }
+    if filled < stripe.len() {
+      break;
+    }
+  }
+
+  let trailer = Trailer {
+    size,
+    // time ordered so the latest of equally readable writes can be told apart
+    write_id: Uuid::now_v7(),
+  };
+  send_all(senders, Bytes::from(trailer.encode())).await;
+  Ok(())
+}
+
+/// Reads the next block of every shard, shards that fail or are corrupt are dropped
+async fn read_stripe(path: &Path, readers: &mut [Option<ShardReader>]) -> Vec<Option<Vec<u8>>> {
+  join_all(
+    readers
+      .iter_mut()
+      .enumerate()
+      .map(|(index, slot)| async move {
+        let reader = slot.as_mut()?;
+        let mut buf = vec![0; BLOCK_SIZE];
+        if let Err(e) = reader.read_exact(&mut buf).await {
+          tracing::warn!("Failed to read shard {index} of {}: {e}", path.display());
+          *slot = None;
+          return None;
+        }
+
+        if !verify_block(&buf) {
+          tracing::warn!("Shard {index} of {} is corrupt", path.display());
+          return None;
+        }
+        buf.truncate(SHARD_SIZE);
+        Some(buf)
+      }),
+  )
+  .await
+}
+
+/// Decodes the stripes of the shard readers and sends `length` bytes starting `skip`
+/// bytes into the first stripe
+async fn decode(
+  path: PathBuf,
+  codec: Arc<ReedSolomon>,
+  mut readers: Vec<Option<ShardReader>>,
+  mut skip: usize,
+  mut length: u64,
+  mut sender: mpsc::Sender<Result<Bytes>>,
+) {
+  while length > 0 {
+    let mut shards = read_stripe(&path, &mut readers).await;
+    let data_shards = codec.data_shard_count();
+    if shards[..data_shards].iter().any(Option::is_none)
+      && let Err(e) = codec.reconstruct_data(&mut shards)
+    {
+      let error = Error::new(
+        ErrorKind::InvalidData,
+        format!("Failed to reconstruct {}: {e}", path.display()),
+      );
+      let _ = sender.send(Err(error)).await;
+      return;
+    }
+
+    let data: Vec<u8> = shards
+      .into_iter()
+      .take(data_shards)
+      .flatten()
+      .flatten()
+      .collect();
+    let end = data.len().min(skip.saturating_add(length as usize));
+    length -= (end - skip) as u64;
+    let chunk = Bytes::from(data).slice(skip..end);
+    skip = 0;
+    if sender.send(Ok(chunk)).await.is_err() {
+      // the reader was dropped
+      return;
+    }
+  }
+}
+
+#[async_trait::async_trait]
+impl Storage for Erasure {
+  async fn create_dir(&self, path: &Path) -> Result<()> {
+    self.shards.modify_all(|shard| shard.create_dir(path)).await
+  }
+
+  async fn delete_dir(&self, path: &Path) -> Result<()> {
+    self.shards.modify_all(|shard| shard.delete_dir(path)).await
+  }
+
+  async fn delete_empty_dir(&self, path: &Path) -> Result<()> {
+    self.shards.delete_empty_dir(path).await
+  }
+
+  async fn list_dir(&self, path: &Path) -> Result<Vec<String>> {
+    let entries = self.list_entries(path).await?;
+    Ok(entries.into_iter().map(|entry| entry.name).collect())
+  }
+
+  async fn list_entries(&self, path: &Path) -> Result<Vec<DirEntry>> {
+    self.shards.list_entries(path).await
+  }
+
+  /// Streams the encoded shards into all healthy directories at once, the write
+  /// succeeds if it reached the write quorum
+  #[instrument(skip(reader))]
+  async fn stream_write_file(
+    &self,
+    path: &Path,
+    reader: &mut (dyn AsyncRead + Unpin + Send),
+  ) -> Result<()> {
+    let (senders, devices, writes) = self.shard_writers(path, |_| true);
+    if devices.len() < self.write_quorum() {
+      return Err(no_healthy_device());
+    }
+
+    let (encoded, results) = join(encode(reader, &self.codec, senders), join_all(writes)).await;
+    encoded?;
+    self
+      .shards
+      .settle(&devices, results, self.write_quorum(), false)
+  }
+
+  async fn stream_read_file(&self, path: &Path) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    self.stream_read_file_range(path, 0, u64::MAX).await
+  }
+
+  /// Only reads and decodes the stripes covering the range
+  async fn stream_read_file_range(
+    &self,
+    path: &Path,
+    offset: u64,
+    length: u64,
+  ) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let located = self.locate(path).await?;
+    let length = length.min(located.size.saturating_sub(offset));
+    if length == 0 {
+      return Ok(Box::new(tokio::io::empty()));
+    }
+
+    let stripe_size = self.stripe_size();
+    let first = offset / stripe_size;
+    let last = (offset + length - 1) / stripe_size;
+    let block_offset = first * BLOCK_SIZE as u64;
+    let block_length = (last - first + 1) * BLOCK_SIZE as u64;
+
+    let readers = join_all(self.shards.all().iter().zip(&located.shards).map(
+      |(device, located)| async move {
+        if !located {
+          return None;
+        }
+        device
+          .storage
+          .stream_read_file_range(path, block_offset, block_length)
+          .await
+          .ok()
+      },
+    ))
+    .await;
+
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(decode(
+      path.to_path_buf(),
+      self.codec.clone(),
+      readers,
+      (offset - first * stripe_size) as usize,
+      length,
+      sender,
+    ));
+    Ok(Box::new(StreamReader::new(receiver)))
+  }
+
+  async fn delete_file(&self, path: &Path) -> Result<()> {
+    self
+      .shards
+      .modify_all(|shard| shard.delete_file(path))
+      .await
+  }
+
+  async fn file_info(&self, path: &Path) -> Result<FileInfo> {
+    Ok(self.locate(path).await?.info)
+  }
+
+  fn degraded(&self) -> Vec<PathBuf> {
+    self.shards.degraded()
+  }
+
+  fn is_redundant(&self) -> bool {
+    true
+  }
+
+  /// Rewrites shards that are corrupt, missing or left over from an older write
+  #[instrument]
+  async fn scrub_file(&self, path: &Path, tracked: bool) -> Result<ScrubReport> {
+    if !tracked && let Some(report) = self.shards.remove_orphaned(path).await {
+      return Ok(report);
+    }
+
+    let located = match self.locate(path).await {
+      Ok(located) => located,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(ScrubReport::default()),
+      Err(e) => return Err(e),
+    };
+    let stripes = located.size.div_ceil(self.stripe_size());
+    let devices = self.shards.all();
+    let intact = join_all(
+      devices
+        .iter()
+        .zip(&located.shards)
+        .map(|(device, located)| async move {
+          *located && verify_shard(&device.storage, path, stripes).await
+        }),
+    )
+    .await;
+
+    let damaged: Vec<_> = (0..devices.len())
+      .filter(|&index| devices[index].is_healthy() && !intact[index])
+      .collect();
+    let mut report = ScrubReport {
+      damaged: damaged.len(),
+      repaired: 0,
+    };
+    if damaged.is_empty() {
+      return Ok(report);
+    }
+    if intact.iter().filter(|intact| **intact).count() < self.data_shards() {
+      tracing::error!(
+        "Not enough intact shards of {} are left to repair it",
+        path.display()
+      );
+      return Ok(report);
+    }
+
+    if let Some(parent) = path.parent() {
+      // a replaced disk may lack the directory, the write reports the failure
+      join_all(
+        damaged
+          .iter()
+          .map(|&index| devices[index].storage.create_dir(parent)),
+      )
+      .await;
+    }
+    let readers = join_all(
+      devices
+        .iter()
+        .zip(&intact)
+        .map(|(device, intact)| async move {
+          if !intact {
+            return None;
+          }
+          device
+            .storage
+            .stream_read_file_range(path, 0, stripes * BLOCK_SIZE as u64)
+            .await
+            .ok()
+        }),
+    )
+    .await;
+
+    let (senders, targets, writes) = self.shard_writers(path, |index| damaged.contains(&index));
+    let trailer = Trailer {
+      size: located.size,
+      write_id: located.write_id,
+    };
+    let (rebuilt, results) = join(
+      rebuild(path, &self.codec, readers, stripes, trailer, senders),
+      join_all(writes),
+    )
+    .await;
+    rebuilt?;
+
+    for (device, result) in targets.iter().zip(results) {
+      match result {
+        Ok(()) => report.repaired += 1,
+        Err(e) => tracing::warn!(
+          "Failed to repair shard of {} in {}: {e}",
+          path.display(),
+          device.storage.base_path().display()
+        ),
+      }
+    }
+    Ok(report)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tokio::fs;
+
+  use super::*;
+
+  fn erasure() -> (Erasure, Vec<PathBuf>) {
+    erasure_with(5, 2)
+  }
+
+  fn erasure_with(shards: usize, parity_shards: usize) -> (Erasure, Vec<PathBuf>) {
+    let dirs: Vec<_> = (0..shards)
+      .map(|_| std::env::temp_dir().join(format!("sagittarius-{}", Uuid::new_v4())))
+      .collect();
+    let erasure = Erasure::new(
+      dirs.iter().cloned().map(NoRaid::new).collect(),
+      parity_shards,
+    )
+    .unwrap();
+    (erasure, dirs)
+  }
+
+  async fn read_range(erasure: &Erasure, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    erasure
+      .stream_read_file_range(Path::new("bucket/file"), offset, length)
+      .await?
+      .read_to_end(&mut buf)
+      .await?;
+    Ok(buf)
+  }
+
+  #[tokio::test]
+  async fn test_erasure_storage() -> Result<()> {
+    let (erasure, dirs) = erasure();
+    erasure.create_dir(Path::new("bucket")).await?;
+
+    let data: Vec<u8> = (0..7 * SHARD_SIZE + 11).map(|i| (i % 251) as u8).collect();
+    erasure.write_file(Path::new("bucket/file"), &data).await?;
+    assert_eq!(erasure.read_file(Path::new("bucket/file")).await?, data);
+    assert_eq!(
+      erasure.file_info(Path::new("bucket/file")).await?.size,
+      data.len() as u64
+    );
+
+    // ranges spanning stripes only decode the stripes they cover
+    let offset = 3 * SHARD_SIZE - 5;
+    assert_eq!(
+      read_range(&erasure, offset as u64, 10).await?,
+      data[offset..offset + 10]
+    );
+    assert_eq!(
+      read_range(&erasure, 5, 4 * SHARD_SIZE as u64).await?,
+      data[5..5 + 4 * SHARD_SIZE]
+    );
+
+    // up to two shards can be missing or corrupt
+    fs::remove_file(dirs[0].join("bucket/file")).await?;
+    let shard = dirs[3].join("bucket/file");
+    let mut corrupt = fs::read(&shard).await?;
+    corrupt[BLOCK_SIZE + 7] ^= 0xff;
+    fs::write(&shard, corrupt).await?;
+    assert_eq!(erasure.read_file(Path::new("bucket/file")).await?, data);
+    assert_eq!(erasure.list_dir(Path::new("bucket")).await?, vec!["file"]);
+
+    fs::remove_file(dirs[1].join("bucket/file")).await?;
+    assert!(erasure.read_file(Path::new("bucket/file")).await.is_err());
+
+    erasure.write_file(Path::new("bucket/empty"), b"").await?;
+    assert!(
+      erasure
+        .read_file(Path::new("bucket/empty"))
+        .await?
+        .is_empty()
+    );
+
+    erasure.delete_file(Path::new("bucket/file")).await?;
+    assert_eq!(
+      erasure
+        .file_info(Path::new("bucket/file"))
+        .await
+        .unwrap_err()
+        .kind(),
+      ErrorKind::NotFound
+    );
+    assert!(erasure.degraded().is_empty());
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_erasure_stale_shard() -> Result<()> {
+    let (erasure, dirs) = erasure();
+    erasure.create_dir(Path::new("bucket")).await?;
+
+    // a shard left over from an older write is not mixed into the newer one
+    erasure.write_file(Path::new("bucket/file"), b"old").await?;
+    let stale = fs::read(dirs[2].join("bucket/file")).await?;
+    erasure
+      .write_file(Path::new("bucket/file"), b"new data")
+      .await?;
+    fs::write(dirs[2].join("bucket/file"), stale).await?;
+    assert_eq!(
+      erasure.read_file(Path::new("bucket/file")).await?,
+      b"new data"
+    );
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_erasure_equal_writes() -> Result<()> {
+    let (erasure, dirs) = erasure_with(4, 2);
+    erasure.create_dir(Path::new("bucket")).await?;
+
+    // with as many shards of an older write as of the newer one the newer one wins
+    erasure.write_file(Path::new("bucket/file"), b"old").await?;
+    let stale = futures::future::try_join_all(
+      dirs[..2]
+        .iter()
+        .map(|dir| fs::read(dir.join("bucket/file"))),
+    )
+    .await?;
+    erasure
+      .write_file(Path::new("bucket/file"), b"new data")
+      .await?;
+    for (dir, shard) in dirs.iter().zip(stale) {
+      fs::write(dir.join("bucket/file"), shard).await?;
+    }
+    for _ in 0..4 {
+      assert_eq!(
+        erasure.read_file(Path::new("bucket/file")).await?,
+        b"new data"
+      );
+    }
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_erasure_write_quorum() -> Result<()> {
+    let (erasure, dirs) = erasure();
+    erasure.create_dir(Path::new("bucket")).await?;
+
+    // a write needs one shard more than the data shards
+    for dir in &dirs[..2] {
+      fs::remove_dir_all(dir).await?;
+      fs::write(dir, b"not a directory").await?;
+    }
+    assert!(
+      erasure
+        .write_file(Path::new("bucket/file"), b"data")
+        .await
+        .is_err()
+    );
+    assert_eq!(erasure.degraded(), dirs[..2].to_vec());
+
+    for dir in &dirs[..2] {
+      fs::remove_file(dir).await?;
+    }
+    for dir in &dirs[2..] {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_erasure_single_parity() {
+    let shards = (0..4).map(|_| NoRaid::new(std::env::temp_dir())).collect();
+    let err = Erasure::new(shards, 1).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[tokio::test]
+  async fn test_erasure_parity_quorum() -> Result<()> {
+    let (erasure, dirs) = erasure_with(5, 4);
+    erasure.create_dir(Path::new("bucket")).await?;
+    erasure.write_file(Path::new("bucket/file"), b"old").await?;
+    let stale = futures::future::try_join_all(
+      dirs[..2]
+        .iter()
+        .map(|dir| fs::read(dir.join("bucket/file"))),
+    )
+    .await?;
+
+    // with more parity than data shards a write needs a majority of the shards
+    for dir in &dirs[..2] {
+      fs::remove_dir_all(dir).await?;
+      fs::write(dir, b"not a directory").await?;
+    }
+    erasure
+      .write_file(Path::new("bucket/file"), b"new data")
+      .await?;
+
+    // the shards of the older write that come back are outnumbered by the newer write
+    for (dir, shard) in dirs.iter().zip(stale) {
+      fs::remove_file(dir).await?;
+      fs::create_dir_all(dir.join("bucket")).await?;
+      fs::write(dir.join("bucket/file"), shard).await?;
+    }
+    let erasure = Erasure::new(dirs.iter().cloned().map(NoRaid::new).collect(), 4)?;
+    assert_eq!(
+      erasure.read_file(Path::new("bucket/file")).await?,
+      b"new data"
+    );
+
+    for dir in &dirs[..3] {
+      fs::remove_dir_all(dir).await?;
+      fs::write(dir, b"not a directory").await?;
+    }
+    assert!(
+      erasure
+        .write_file(Path::new("bucket/file"), b"newer data")
+        .await
+        .is_err()
+    );
+
+    for dir in &dirs[..3] {
+      fs::remove_file(dir).await?;
+    }
+    for dir in &dirs[3..] {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_erasure_scrub() -> Result<()> {
+    let (erasure, dirs) = erasure();
+    erasure.create_dir(Path::new("bucket")).await?;
+    let data: Vec<u8> = (0..4 * SHARD_SIZE).map(|i| (i % 13) as u8).collect();
+    erasure.write_file(Path::new("bucket/file"), &data).await?;
+    let shards =
+      futures::future::try_join_all(dirs.iter().map(|dir| fs::read(dir.join("bucket/file"))))
+        .await?;
+
+    // a corrupt data shard and a missing parity shard are rebuilt
+    let mut corrupt = shards[1].clone();
+    corrupt[3] ^= 0x01;
+    fs::write(dirs[1].join("bucket/file"), corrupt).await?;
+    fs::remove_dir_all(dirs[4].join("bucket")).await?;
+    let scrub = erasure.scrub_file(Path::new("bucket/file"), true).await?;
+    assert_eq!(
+      scrub,
+      ScrubReport {
+        damaged: 2,
+        repaired: 2
+      }
+    );
+    for (dir, shard) in dirs.iter().zip(&shards) {
+      assert_eq!(&fs::read(dir.join("bucket/file")).await?, shard);
+    }
+    assert_eq!(
+      erasure.scrub_file(Path::new("bucket/file"), true).await?,
+      ScrubReport::default()
+    );
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,570 @@
+use std::{
+  io::{Error, ErrorKind, Result},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use axum::body::Bytes;
+use chrono::Utc;
+use futures::{
+  SinkExt,
+  channel::mpsc,
+  future::{join, join_all},
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+use tracing::instrument;
+
+use crate::s3::storage::{
+  DirEntry, FileInfo, ScrubReport, Storage,
+  devices::{Devices, no_healthy_device},
+  no_raid::NoRaid,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAGIC: &[u8; 4] = b"SGMR";
+/// CRC32 of the content and write id, the write id and magic
+const TRAILER_SIZE: u64 = 4 + 8 + 4;
+
+/// Keeps a full copy of every file in each of its directories (RAID1)
+///
+/// Every copy ends with a trailer holding the id of the write and the CRC32 of its
+/// content so scrubbing can tell intact copies from corrupt or stale ones, reads are
+/// served by a healthy copy of the latest write
+///
+/// A copy that fails a write is degraded and the write continues on the remaining
+/// copies, so a mirror stays writable as long as one copy is left, degraded copies
+/// missed writes and are brought up to date by the scrubber once they are back
+#[derive(Debug)]
+pub struct Mirror {
+  copies: Devices,
+}
+
+impl Mirror {
+  pub fn new(copies: Vec<NoRaid>) -> Self {
+    Self {
+      copies: Devices::new(copies),
+    }
+  }
+
+  /// Finds the healthy copies that hold the latest write of a file
+  async fn latest(&self, path: &Path) -> Result<Vec<(&NoRaid, FileInfo)>> {
+    let copies: Vec<_> = self.copies.healthy().collect();
+    let trailers = join_all(copies.iter().map(|copy| read_trailer(&copy.storage, path))).await;
+
+    let mut latest = Vec::new();
+    let mut latest_id = None;
+    let mut error = None;
+    for (copy, trailer) in copies.iter().zip(trailers) {
+      match trailer {
+        Ok((trailer, info)) => {
+          if Some(trailer.write_id) > latest_id {
+            latest.clear();
+            latest_id = Some(trailer.write_id);
+          }
+          if Some(trailer.write_id) == latest_id {
+            latest.push((&copy.storage, info));
+          }
+        }
+        // a missing file is reported over other errors
+        Err(e) if e.kind() == ErrorKind::NotFound || error.is_none() => error = Some(e),
+        Err(_) => (),
+      }
+    }
+
+    if latest.is_empty() {
+      return Err(error.unwrap_or_else(no_healthy_device));
+    }
+    Ok(latest)
+  }
+}
+
+/// Id of a new write, ids of later writes are larger even if the clock goes back
+fn next_write_id() -> u64 {
+  static LAST: AtomicU64 = AtomicU64::new(0);
+  let now = Utc::now().timestamp_micros() as u64;
+  let previous = LAST
+    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+      Some(now.max(last + 1))
+    })
+    .unwrap_or_default();
+  now.max(previous + 1)
+}
+
+/// Forwards the reader to every copy followed by the trailer, read errors are
+/// forwarded as well so no copy commits a truncated file
+async fn pump(
+  reader: &mut (dyn AsyncRead + Unpin + Send),
+  mut senders: Vec<mpsc::Sender<Result<Bytes>>>,
+) -> Result<()> {
+  let mut buf = vec![0; CHUNK_SIZE];
+  let mut crc = crc32fast::Hasher::new();
+  loop {
+    let read = reader.read(&mut buf).await;
+    if matches!(read, Ok(0)) {
+      let trailer = Trailer::new(crc, next_write_id()).encode();
+      for sender in &mut senders {
+        let _ = sender.send(Ok(trailer.clone())).await;
+      }
+      return Ok(());
+    }
+    if let Ok(read) = &read {
+      crc.update(&buf[..*read]);
+    }
+
+    for sender in &mut senders {
+      let chunk = match &read {
+        Ok(read) => Ok(Bytes::copy_from_slice(&buf[..*read])),
+        Err(e) => Err(Error::new(e.kind(), e.to_string())),
+      };
+      // copies that failed already dropped their receiver
+      let _ = sender.send(chunk).await;
+    }
+
+    read?;
+  }
+}
+
+struct Trailer {
+  crc: u32,
+  write_id: u64,
+}
+
+impl Trailer {
+  /// Completes the CRC32 of the content with the write id
+  fn new(mut crc: crc32fast::Hasher, write_id: u64) -> Self {
+    crc.update(&write_id.to_le_bytes());
+    Self {
+      crc: crc.finalize(),
+      write_id,
+    }
+  }
+
+  fn encode(&self) -> Bytes {
+    let mut trailer = Vec::with_capacity(TRAILER_SIZE as usize);
+    trailer.extend(self.crc.to_le_bytes());
+    trailer.extend(self.write_id.to_le_bytes());
+    trailer.extend(MAGIC);
+    Bytes::from(trailer)
+  }
+
+  fn decode(buf: &[u8; TRAILER_SIZE as usize]) -> Option<Self> {
+    let (crc, rest) = buf.split_at(4);
+    let (write_id, magic) = rest.split_at(8);
+    if magic != MAGIC {
+      return None;
+    }
+    Some(Self {
+      crc: u32::from_le_bytes(crc.try_into().ok()?),
+      write_id: u64::from_le_bytes(write_id.try_into().ok()?),
+    })
+  }
+}
+
+/// Reads the trailer of a copy, the info is that of the content without the trailer
+async fn read_trailer(copy: &NoRaid, path: &Path) -> Result<(Trailer, FileInfo)> {
+  let info = copy.file_info(path).await?;
+  let corrupt = || {
+    Error::new(
+      ErrorKind::InvalidData,
+      format!(
+        "Copy of {} in {} is corrupt",
+        path.display(),
+        copy.base_path().display()
+      ),
+    )
+  };
+  let size = info.size.checked_sub(TRAILER_SIZE).ok_or_else(corrupt)?;
+
+  let mut buf = [0; TRAILER_SIZE as usize];
+  copy
+    .stream_read_file_range(path, size, TRAILER_SIZE)
+    .await?
+    .read_exact(&mut buf)
+    .await?;
+  let trailer = Trailer::decode(&buf).ok_or_else(corrupt)?;
+  Ok((
+    trailer,
+    FileInfo {
+      size,
+      modified: info.modified,
+    },
+  ))
+}
+
+enum CopyState {
+  Intact { write_id: u64 },
+  Missing,
+  Damaged,
+}
+
+/// Recomputes the checksum of a copy and compares it to its trailer
+async fn verify_copy(copy: &NoRaid, path: &Path) -> CopyState {
+  let result = async {
+    let (trailer, info) = read_trailer(copy, path).await?;
+
+    let mut reader = copy.stream_read_file_range(path, 0, info.size).await?;
+    let mut crc = crc32fast::Hasher::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+      match reader.read(&mut buf).await? {
+        0 => break,
+        read => crc.update(&buf[..read]),
+      }
+    }
+
+    let intact = Trailer::new(crc, trailer.write_id).crc == trailer.crc;
+    Ok::<_, Error>(intact.then_some(trailer.write_id))
+  };
+
+  match result.await {
+    Ok(Some(write_id)) => CopyState::Intact { write_id },
+    Err(e) if e.kind() == ErrorKind::NotFound => CopyState::Missing,
+    Ok(None) | Err(_) => CopyState::Damaged,
+  }
+}
+
+#[async_trait::async_trait]
+impl Storage for Mirror {
+  async fn create_dir(&self, path: &Path) -> Result<()> {
+    self.copies.modify_all(|copy| copy.create_dir(path)).await
+  }
+
+  async fn delete_dir(&self, path: &Path) -> Result<()> {
+    self.copies.modify_all(|copy| copy.delete_dir(path)).await
+  }
+
+  async fn delete_empty_dir(&self, path: &Path) -> Result<()> {
+    self.copies.delete_empty_dir(path).await
+  }
+
+  async fn list_dir(&self, path: &Path) -> Result<Vec<String>> {
+    let entries = self.list_entries(path).await?;
+    Ok(entries.into_iter().map(|entry| entry.name).collect())
+  }
+
+  async fn list_entries(&self, path: &Path) -> Result<Vec<DirEntry>> {
+    self.copies.list_entries(path).await
+  }
+
+  /// Streams the reader into all healthy copies at once, the write succeeds if it
+  /// reached at least one of them
+  #[instrument(skip(reader))]
+  async fn stream_write_file(
+    &self,
+    path: &Path,
+    reader: &mut (dyn AsyncRead + Unpin + Send),
+  ) -> Result<()> {
+    let copies: Vec<_> = self.copies.healthy().collect();
+    if copies.is_empty() {
+      return Err(no_healthy_device());
+    }
+    let (senders, receivers): (Vec<_>, Vec<_>) = copies
+      .iter()
+      .map(|_| mpsc::channel::<Result<Bytes>>(1))
+      .unzip();
+
+    let writes = join_all(copies.iter().zip(receivers).map(|(copy, receiver)| async {
+      let mut reader = StreamReader::new(receiver);
+      copy.storage.stream_write_file(path, &mut reader).await
+    }));
+
+    let (pumped, results) = join(pump(reader, senders), writes).await;
+    pumped?;
+    self.copies.settle(&copies, results, 1, false)
+  }
+
+  async fn stream_read_file(&self, path: &Path) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    self.stream_read_file_range(path, 0, u64::MAX).await
+  }
+
+  /// Reads from the first copy of the latest write that can be opened
+  async fn stream_read_file_range(
+    &self,
+    path: &Path,
+    offset: u64,
+    length: u64,
+  ) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let mut error = None;
+    for (copy, info) in self.latest(path).await? {
+      let length = length.min(info.size.saturating_sub(offset));
+      match copy.stream_read_file_range(path, offset, length).await {
+        Ok(reader) => return Ok(reader),
+        Err(e) => error = Some(e),
+      }
+    }
+    Err(error.unwrap_or_else(no_healthy_device))
+  }
+
+  async fn delete_file(&self, path: &Path) -> Result<()> {
+    self.copies.modify_all(|copy| copy.delete_file(path)).await
+  }
+
+  async fn file_info(&self, path: &Path) -> Result<FileInfo> {
+    let mut latest = self.latest(path).await?;
+    Ok(latest.swap_remove(0).1)
+  }
+
+  fn degraded(&self) -> Vec<PathBuf> {
+    self.copies.degraded()
+  }
+
+  fn is_redundant(&self) -> bool {
+    true
+  }
+
+  /// Rewrites every copy that is corrupt, missing or older than the latest intact copy
+  #[instrument]
+  async fn scrub_file(&self, path: &Path, tracked: bool) -> Result<ScrubReport> {
+    if !tracked && let Some(report) = self.copies.remove_orphaned(path).await {
+      return Ok(report);
+    }
+
+    let copies: Vec<_> = self.copies.healthy().collect();
+    let states = join_all(copies.iter().map(|copy| verify_copy(&copy.storage, path))).await;
+
+    let source = copies
+      .iter()
+      .zip(&states)
+      .filter_map(|(copy, state)| match state {
+        CopyState::Intact { write_id } => Some((copy, *write_id)),
+        _ => None,
+      })
+      .max_by_key(|(_, write_id)| *write_id);
+    let Some((source, source_id)) = source else {
+      let damaged = states
+        .iter()
+        .filter(|state| !matches!(state, CopyState::Missing))
+        .count();
+      if damaged > 0 {
+        tracing::error!("No intact copy of {} is left", path.display());
+      }
+      return Ok(ScrubReport {
+        damaged,
+        repaired: 0,
+      });
+    };
+
+    let mut report = ScrubReport::default();
+    for (copy, state) in copies.iter().zip(&states) {
+      if matches!(state, CopyState::Intact { write_id } if *write_id == source_id) {
+        continue;
+      }
+      report.damaged += 1;
+
+      let repair = async {
+        if let Some(parent) = path.parent() {
+          copy.storage.create_dir(parent).await?;
+        }
+        let mut reader = source.storage.stream_read_file(path).await?;
+        copy.storage.stream_write_file(path, &mut reader).await
+      };
+      match repair.await {
+        Ok(()) => report.repaired += 1,
+        Err(e) => tracing::warn!(
+          "Failed to repair copy of {} in {}: {e}",
+          path.display(),
+          copy.storage.base_path().display()
+        ),
+      }
+    }
+    Ok(report)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use tokio::fs;
+  use uuid::Uuid;
+
+  use super::*;
+
+  fn mirror() -> (Mirror, Vec<PathBuf>) {
+    mirror_with(3)
+  }
+
+  fn mirror_with(copies: usize) -> (Mirror, Vec<PathBuf>) {
+    let dirs: Vec<_> = (0..copies)
+      .map(|_| std::env::temp_dir().join(format!("sagittarius-{}", Uuid::new_v4())))
+      .collect();
+    let mirror = Mirror::new(dirs.iter().cloned().map(NoRaid::new).collect());
+    (mirror, dirs)
+  }
+
+  #[tokio::test]
+  async fn test_mirror_storage() -> Result<()> {
+    let (mirror, dirs) = mirror();
+    mirror.create_dir(Path::new("bucket")).await?;
+
+    let data = vec![7u8; 3 * CHUNK_SIZE + 5];
+    mirror.write_file(Path::new("bucket/file"), &data).await?;
+    for dir in &dirs {
+      let copy = fs::read(dir.join("bucket/file")).await?;
+      assert_eq!(copy[..data.len()], data);
+      assert_eq!(copy.len(), data.len() + TRAILER_SIZE as usize);
+    }
+
+    // reads and listings fall back to copies that still have the file
+    fs::remove_file(dirs[0].join("bucket/file")).await?;
+    assert_eq!(mirror.read_file(Path::new("bucket/file")).await?, data);
+    assert_eq!(mirror.list_dir(Path::new("bucket")).await?, vec!["file"]);
+    assert_eq!(
+      mirror.file_info(Path::new("bucket/file")).await?.size,
+      data.len() as u64
+    );
+
+    mirror.delete_file(Path::new("bucket/file")).await?;
+    assert!(mirror.read_file(Path::new("bucket/file")).await.is_err());
+    assert!(mirror.degraded().is_empty());
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_mirror_degraded() -> Result<()> {
+    let (mirror, dirs) = mirror();
+    mirror.create_dir(Path::new("bucket")).await?;
+
+    // a copy that can not be written to is degraded, the others keep working
+    fs::remove_dir_all(&dirs[1]).await?;
+    fs::write(&dirs[1], b"not a directory").await?;
+    mirror.write_file(Path::new("bucket/file"), b"data").await?;
+    assert_eq!(mirror.degraded(), vec![dirs[1].clone()]);
+    assert_eq!(mirror.read_file(Path::new("bucket/file")).await?, b"data");
+
+    // writes continue on the last copy that is left
+    fs::remove_dir_all(&dirs[2]).await?;
+    fs::write(&dirs[2], b"not a directory").await?;
+    mirror.write_file(Path::new("bucket/file"), b"new").await?;
+    mirror
+      .write_file(Path::new("bucket/file"), b"newer")
+      .await?;
+    assert_eq!(mirror.read_file(Path::new("bucket/file")).await?, b"newer");
+    assert_eq!(mirror.degraded(), dirs[1..].to_vec());
+
+    fs::remove_file(&dirs[2]).await?;
+    fs::create_dir(&dirs[2]).await?;
+    fs::remove_file(&dirs[1]).await?;
+    for dir in [&dirs[0], &dirs[2]] {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_mirror_two_copies() -> Result<()> {
+    let (mirror, dirs) = mirror_with(2);
+    mirror.create_dir(Path::new("bucket")).await?;
+    mirror.write_file(Path::new("bucket/file"), b"old").await?;
+
+    // losing one of two copies does not make the mirror read only
+    fs::remove_dir_all(&dirs[1]).await?;
+    fs::write(&dirs[1], b"not a directory").await?;
+    mirror.write_file(Path::new("bucket/file"), b"new").await?;
+    mirror
+      .write_file(Path::new("bucket/other"), b"data")
+      .await?;
+    assert_eq!(mirror.read_file(Path::new("bucket/file")).await?, b"new");
+
+    // the copy that comes back is brought up to date by scrubbing
+    fs::remove_file(&dirs[1]).await?;
+    fs::create_dir_all(dirs[1].join("bucket")).await?;
+    let mirror = Mirror::new(dirs.iter().cloned().map(NoRaid::new).collect());
+    for file in ["bucket/file", "bucket/other"] {
+      mirror.scrub_file(Path::new(file), true).await?;
+      assert_eq!(
+        fs::read(dirs[1].join(file)).await?,
+        fs::read(dirs[0].join(file)).await?
+      );
+    }
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_mirror_stale() -> Result<()> {
+    let (mirror, dirs) = mirror();
+    mirror.create_dir(Path::new("bucket")).await?;
+    mirror.write_file(Path::new("bucket/file"), b"old").await?;
+    let stale = fs::read(dirs[0].join("bucket/file")).await?;
+    mirror
+      .write_file(Path::new("bucket/file"), b"new data")
+      .await?;
+
+    // a copy that missed the latest write is never read and gets rewritten
+    fs::write(dirs[0].join("bucket/file"), &stale).await?;
+    assert_eq!(
+      mirror.read_file(Path::new("bucket/file")).await?,
+      b"new data"
+    );
+    assert_eq!(mirror.file_info(Path::new("bucket/file")).await?.size, 8);
+    let scrub = mirror.scrub_file(Path::new("bucket/file"), true).await?;
+    assert_eq!(
+      scrub,
+      ScrubReport {
+        damaged: 1,
+        repaired: 1
+      }
+    );
+    let latest = fs::read(dirs[1].join("bucket/file")).await?;
+    assert_eq!(fs::read(dirs[0].join("bucket/file")).await?, latest);
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_mirror_scrub() -> Result<()> {
+    let (mirror, dirs) = mirror();
+    mirror.create_dir(Path::new("bucket")).await?;
+    mirror.write_file(Path::new("bucket/file"), b"data").await?;
+    let scrub = mirror.scrub_file(Path::new("bucket/file"), true).await?;
+    assert_eq!(scrub, ScrubReport::default());
+
+    // corrupt and missing copies are restored from an intact one
+    fs::write(
+      dirs[0].join("bucket/file"),
+      b"dat4\0\0\0\0\0\0\0\0\0\0\0\0SGMR",
+    )
+    .await?;
+    fs::remove_dir_all(dirs[2].join("bucket")).await?;
+    let scrub = mirror.scrub_file(Path::new("bucket/file"), true).await?;
+    assert_eq!(
+      scrub,
+      ScrubReport {
+        damaged: 2,
+        repaired: 2
+      }
+    );
+    let intact = fs::read(dirs[1].join("bucket/file")).await?;
+    for dir in &dirs {
+      assert_eq!(fs::read(dir.join("bucket/file")).await?, intact);
+    }
+
+    // copies left over from a delete are removed once the file is no longer tracked
+    fs::remove_file(dirs[0].join("bucket/file")).await?;
+    fs::remove_file(dirs[1].join("bucket/file")).await?;
+    let scrub = mirror.scrub_file(Path::new("bucket/file"), false).await?;
+    assert_eq!(
+      scrub,
+      ScrubReport {
+        damaged: 1,
+        repaired: 1
+      }
+    );
+    assert!(!fs::try_exists(dirs[2].join("bucket/file")).await?);
+
+    for dir in dirs {
+      fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+  }
+}
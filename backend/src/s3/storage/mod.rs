@@ -1,28 +1,67 @@
 use std::fmt::Debug;
+use std::io::{Error, ErrorKind};
 use std::path::{self, Path};
 use std::{io::Result, path::PathBuf};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
-use crate::s3::BUCKET_DIR;
+use crate::config::EnvConfig;
+use crate::db::Connection;
 use crate::s3::interface::S3Interface;
+use crate::s3::storage::no_raid::NoRaid;
+use crate::s3::{BUCKET_DIR, MULTIPART_DIR};
 
+mod devices;
+pub mod erasure;
+pub mod mirror;
 pub mod no_raid;
+pub mod walk;
 
 #[async_trait::async_trait]
 pub trait Storage: Debug {
   async fn create_dir(&self, path: &Path) -> Result<()>;
   async fn delete_dir(&self, path: &Path) -> Result<()>;
+  /// Removes a directory only if it is empty, fails with `DirectoryNotEmpty` otherwise
+  async fn delete_empty_dir(&self, path: &Path) -> Result<()>;
   async fn list_dir(&self, path: &Path) -> Result<Vec<String>>;
+  async fn list_entries(&self, path: &Path) -> Result<Vec<DirEntry>>;
   async fn stream_write_file(
     &self,
     path: &Path,
     reader: &mut (dyn AsyncRead + Unpin + Send),
   ) -> Result<()>;
   async fn stream_read_file(&self, path: &Path) -> Result<Box<dyn AsyncRead + Unpin + Send>>;
+  /// Reads `length` bytes starting at `offset` without reading the bytes before
+  async fn stream_read_file_range(
+    &self,
+    path: &Path,
+    offset: u64,
+    length: u64,
+  ) -> Result<Box<dyn AsyncRead + Unpin + Send>>;
   async fn delete_file(&self, path: &Path) -> Result<()>;
+  async fn file_info(&self, path: &Path) -> Result<FileInfo>;
+
+  /// Directories of redundant storage that failed and no longer receive writes
+  fn degraded(&self) -> Vec<PathBuf> {
+    Vec::new()
+  }
+
+  /// Whether files are stored with redundancy that can be scrubbed
+  fn is_redundant(&self) -> bool {
+    false
+  }
+
+  /// Verifies the checksums of all copies or shards of a file and rewrites
+  /// damaged or missing ones from the intact ones
+  ///
+  /// A file that is no longer `tracked` by the metadata store and is missing from
+  /// some directories was deleted, its remaining copies are removed instead
+  async fn scrub_file(&self, _path: &Path, _tracked: bool) -> Result<ScrubReport> {
+    Ok(ScrubReport::default())
+  }
 
   async fn write_file(&self, path: &Path, data: &[u8]) -> Result<()> {
     self.stream_write_file(path, &mut &data[..]).await
@@ -36,26 +75,83 @@ pub trait Storage: Debug {
   }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirEntry {
+  pub name: String,
+  pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+  pub size: u64,
+  pub modified: DateTime<Utc>,
+}
+
+/// Outcome of scrubbing a single file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScrubReport {
+  /// copies or shards that were corrupt, missing or stale
+  pub damaged: usize,
+  pub repaired: usize,
+}
+
 #[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
 pub enum StorageType {
   NoRaid,
+  /// full copies in every directory of `storage_paths`
+  Mirror,
+  /// data and `storage_parity_shards` parity shards striped across `storage_paths`
+  Erasure,
 }
 
 impl StorageType {
-  pub async fn storage(&self, base_path: PathBuf) -> Result<S3Interface> {
-    let base_path = path::absolute(base_path)?;
-    if !base_path.exists() {
-      fs::create_dir_all(&base_path).await?;
-    }
-    let bucket_path = base_path.join(BUCKET_DIR);
-    if !bucket_path.exists() {
-      fs::create_dir_all(&bucket_path).await?;
+  pub async fn storage(&self, config: &EnvConfig, db: Connection) -> Result<S3Interface> {
+    Ok(match self {
+      StorageType::NoRaid => S3Interface::new(no_raid(&config.storage_path).await?, db),
+      StorageType::Mirror => {
+        if config.storage_paths.len() < 2 {
+          return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Mirror storage requires at least two STORAGE_PATHS",
+          ));
+        }
+
+        let mut copies = Vec::new();
+        for path in &config.storage_paths {
+          copies.push(no_raid(path).await?);
+        }
+        S3Interface::new(mirror::Mirror::new(copies), db)
+      }
+      StorageType::Erasure => {
+        let mut shards = Vec::new();
+        for path in &config.storage_paths {
+          shards.push(no_raid(path).await?);
+        }
+        let erasure = erasure::Erasure::new(shards, config.storage_parity_shards)?;
+        S3Interface::new(erasure, db)
+      }
+    })
+  }
+}
+
+/// Prepares a directory for files and removes leftovers of interrupted writes
+async fn no_raid(base_path: &Path) -> Result<NoRaid> {
+  let base_path = path::absolute(base_path)?;
+  for dir in [BUCKET_DIR, MULTIPART_DIR] {
+    let dir_path = base_path.join(dir);
+    if !dir_path.exists() {
+      fs::create_dir_all(&dir_path).await?;
     }
+  }
 
-    Ok(S3Interface::new(match self {
-      StorageType::NoRaid => no_raid::NoRaid::new(base_path),
-    }))
+  let storage = NoRaid::new(base_path);
+  for dir in [BUCKET_DIR, MULTIPART_DIR] {
+    let removed = storage.cleanup_temp_files(Path::new(dir)).await?;
+    if removed > 0 {
+      tracing::warn!("Removed {removed} temp files of interrupted writes in {dir}");
+    }
   }
+  Ok(storage)
 }
 
 #[cfg(test)]
@@ -71,6 +167,11 @@ mod test {
     storage.create_dir(Path::new("test_bucket")).await?;
     let buckets = storage.list_dir(Path::new("")).await?;
     assert!(buckets.contains(&"test_bucket".to_string()));
+    let entries = storage.list_entries(Path::new("")).await?;
+    assert!(entries.contains(&DirEntry {
+      name: "test_bucket".to_string(),
+      is_dir: true,
+    }));
 
     // Test write_file and read_file
     let data = b"Hello, world!";
@@ -82,6 +183,28 @@ mod test {
       .await?;
     assert_eq!(data.to_vec(), read_data);
 
+    // Test stream_read_file_range
+    let mut range = Vec::new();
+    storage
+      .stream_read_file_range(Path::new("test_bucket/hello.txt"), 7, 5)
+      .await?
+      .read_to_end(&mut range)
+      .await?;
+    assert_eq!(range, b"world");
+
+    // Test file_info
+    let info = storage
+      .file_info(Path::new("test_bucket/hello.txt"))
+      .await?;
+    assert_eq!(info.size, data.len() as u64);
+
+    // Test delete_empty_dir keeps directories with entries
+    let err = storage
+      .delete_empty_dir(Path::new("test_bucket"))
+      .await
+      .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DirectoryNotEmpty);
+
     // Test delete_file
     storage
       .delete_file(Path::new("test_bucket/hello.txt"))
@@ -1,14 +1,18 @@
 use std::{
-  io::{Error, ErrorKind, Result},
-  path::{self, Path, PathBuf},
+  io::{Error, ErrorKind, Result, SeekFrom},
+  path::{Component, Path, PathBuf},
 };
 
 use tokio::{
   fs,
-  io::{self, AsyncRead},
+  io::{self, AsyncRead, AsyncReadExt, AsyncSeekExt},
 };
+use uuid::Uuid;
 
-use crate::s3::storage::Storage;
+use crate::s3::storage::{DirEntry, FileInfo, Storage};
+
+/// Prefix of files that are still being written, they are renamed into place once complete
+pub const TEMP_PREFIX: &str = ".sagittarius-tmp-";
 
 #[derive(Debug)]
 pub struct NoRaid {
@@ -20,20 +24,57 @@ impl NoRaid {
     Self { base_path }
   }
 
-  async fn full_path(&self, path: &Path) -> Result<PathBuf> {
-    let path = path::absolute(self.base_path.join(path))?;
+  pub fn base_path(&self) -> &Path {
+    &self.base_path
+  }
 
-    if !path.starts_with(&self.base_path) {
+  async fn full_path(&self, path: &Path) -> Result<PathBuf> {
+    // `..` is not resolved by a lexical prefix check, so only plain names are accepted
+    if !path
+      .components()
+      .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
       return Err(Error::new(
         ErrorKind::PermissionDenied,
         "Access outside of base path is not allowed",
       ));
     }
 
-    Ok(path)
+    Ok(self.base_path.join(path))
+  }
+
+  /// Removes temp files left behind by writes that were interrupted by a crash
+  pub async fn cleanup_temp_files(&self, path: &Path) -> Result<usize> {
+    let mut removed = 0;
+    let mut pending = vec![self.full_path(path).await?];
+
+    while let Some(dir) = pending.pop() {
+      let mut entries = fs::read_dir(dir).await?;
+      while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+          pending.push(entry.path());
+        } else if is_temp_file(&entry.file_name().to_string_lossy()) {
+          fs::remove_file(entry.path()).await?;
+          removed += 1;
+        }
+      }
+    }
+
+    Ok(removed)
   }
 }
 
+fn is_temp_file(name: &str) -> bool {
+  name.starts_with(TEMP_PREFIX)
+}
+
+/// Writes the reader into a new file and flushes it to disk
+async fn write_synced(path: &Path, reader: &mut (dyn AsyncRead + Unpin + Send)) -> Result<()> {
+  let mut file = fs::File::create(path).await?;
+  io::copy(reader, &mut file).await?;
+  file.sync_all().await
+}
+
 #[async_trait::async_trait]
 impl Storage for NoRaid {
   async fn create_dir(&self, path: &Path) -> Result<()> {
@@ -46,6 +87,11 @@ impl Storage for NoRaid {
     fs::remove_dir_all(full_path).await
   }
 
+  async fn delete_empty_dir(&self, path: &Path) -> Result<()> {
+    let full_path = self.full_path(path).await?;
+    fs::remove_dir(full_path).await
+  }
+
   async fn list_dir(&self, path: &Path) -> Result<Vec<String>> {
     let full_path = self.full_path(path).await?;
     let mut entries = fs::read_dir(full_path).await?;
@@ -58,15 +104,46 @@ impl Storage for NoRaid {
     Ok(names)
   }
 
+  async fn list_entries(&self, path: &Path) -> Result<Vec<DirEntry>> {
+    let full_path = self.full_path(path).await?;
+    let mut entries = fs::read_dir(full_path).await?;
+    let mut result = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+      if let Some(name) = entry.file_name().to_str()
+        && !is_temp_file(name)
+      {
+        result.push(DirEntry {
+          name: name.to_string(),
+          is_dir: entry.file_type().await?.is_dir(),
+        });
+      }
+    }
+    Ok(result)
+  }
+
   async fn stream_write_file(
     &self,
     path: &Path,
     reader: &mut (dyn AsyncRead + Unpin + Send),
   ) -> Result<()> {
     let full_path = self.full_path(path).await?;
-    let mut file = fs::File::create(full_path).await?;
-    io::copy(reader, &mut file).await?;
-    Ok(())
+    let parent = full_path
+      .parent()
+      .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "File has no parent directory"))?;
+    // the temp file is in the same directory so the rename can not cross filesystems
+    let temp_path = parent.join(format!("{TEMP_PREFIX}{}", Uuid::new_v4()));
+
+    let result = match write_synced(&temp_path, reader).await {
+      Ok(()) => fs::rename(&temp_path, &full_path).await,
+      Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+      let _ = fs::remove_file(&temp_path).await;
+      return Err(e);
+    }
+
+    // persist the rename itself
+    fs::File::open(parent).await?.sync_all().await
   }
 
   async fn stream_read_file(&self, path: &Path) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
@@ -75,8 +152,91 @@ impl Storage for NoRaid {
     Ok(Box::new(file))
   }
 
+  async fn stream_read_file_range(
+    &self,
+    path: &Path,
+    offset: u64,
+    length: u64,
+  ) -> Result<Box<dyn AsyncRead + Unpin + Send>> {
+    let full_path = self.full_path(path).await?;
+    let mut file = fs::File::open(full_path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    Ok(Box::new(file.take(length)))
+  }
+
   async fn delete_file(&self, path: &Path) -> Result<()> {
     let full_path = self.full_path(path).await?;
     fs::remove_file(full_path).await
   }
+
+  async fn file_info(&self, path: &Path) -> Result<FileInfo> {
+    let full_path = self.full_path(path).await?;
+    let metadata = fs::metadata(full_path).await?;
+    if !metadata.is_file() {
+      return Err(Error::new(ErrorKind::NotFound, "Not a file"));
+    }
+
+    Ok(FileInfo {
+      size: metadata.len(),
+      modified: metadata.modified()?.into(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_atomic_write() -> Result<()> {
+    let temp_dir = std::env::temp_dir().join(format!("sagittarius-{}", Uuid::new_v4()));
+    let storage = NoRaid::new(temp_dir.clone());
+    storage.create_dir(Path::new("dir")).await?;
+    storage.write_file(Path::new("dir/file"), b"old").await?;
+
+    // a failed write leaves the previous content untouched
+    assert!(
+      storage
+        .stream_write_file(Path::new("dir/file"), &mut FailingReader)
+        .await
+        .is_err()
+    );
+    assert_eq!(storage.read_file(Path::new("dir/file")).await?, b"old");
+    assert_eq!(storage.list_dir(Path::new("dir")).await?, vec!["file"]);
+
+    // leftovers of a crash are hidden from listings and removed on cleanup
+    fs::write(
+      temp_dir.join("dir").join(format!("{TEMP_PREFIX}crash")),
+      b"x",
+    )
+    .await?;
+    assert_eq!(storage.list_entries(Path::new("dir")).await?.len(), 1);
+    assert_eq!(storage.cleanup_temp_files(Path::new("")).await?, 1);
+    assert_eq!(storage.list_dir(Path::new("dir")).await?, vec!["file"]);
+
+    fs::remove_dir_all(temp_dir).await?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_outside_base_path() {
+    let storage = NoRaid::new(std::env::temp_dir().join("sagittarius-base"));
+
+    for path in ["..", "dir/../../escape", "/etc/passwd"] {
+      let err = storage.file_info(Path::new(path)).await.unwrap_err();
+      assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+  }
+
+  struct FailingReader;
+
+  impl AsyncRead for FailingReader {
+    fn poll_read(
+      self: std::pin::Pin<&mut Self>,
+      _: &mut std::task::Context<'_>,
+      _: &mut io::ReadBuf<'_>,
+    ) -> std::task::Poll<Result<()>> {
+      std::task::Poll::Ready(Err(Error::other("connection reset")))
+    }
+  }
 }
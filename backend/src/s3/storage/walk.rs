@@ -0,0 +1,198 @@
+use std::{
+  io::{ErrorKind, Result},
+  path::PathBuf,
+};
+
+use crate::s3::storage::Storage;
+
+/// Enumerates all files below a root directory as `/` separated keys
+/// in lexicographic order, which is the order s3 lists objects in
+///
+/// Directories are only expanded when they can contain keys matching
+/// the prefix that come after `start_after`
+pub struct KeyWalker<'s> {
+  storage: &'s (dyn Storage + Send + Sync),
+  root: PathBuf,
+  prefix: String,
+  start_after: Option<String>,
+  /// stack of entries that still have to be visited, the smallest key is on top
+  pending: Vec<Pending>,
+}
+
+#[derive(Debug)]
+enum Pending {
+  /// key prefix of a directory including the trailing `/`
+  Dir(String),
+  File(String),
+}
+
+impl Pending {
+  fn key(&self) -> &str {
+    match self {
+      Pending::Dir(key) | Pending::File(key) => key,
+    }
+  }
+}
+
+impl<'s> KeyWalker<'s> {
+  pub fn new(
+    storage: &'s (dyn Storage + Send + Sync),
+    root: PathBuf,
+    prefix: &str,
+    start_after: Option<&str>,
+  ) -> Self {
+    Self {
+      storage,
+      root,
+      prefix: prefix.to_string(),
+      start_after: start_after.map(|s| s.to_string()),
+      pending: vec![Pending::Dir(String::new())],
+    }
+  }
+
+  pub async fn next(&mut self) -> Result<Option<String>> {
+    while let Some(pending) = self.pending.pop() {
+      match pending {
+        Pending::File(key) => return Ok(Some(key)),
+        Pending::Dir(dir) => {
+          let entries = match self.storage.list_entries(&self.root.join(&dir)).await {
+            Ok(entries) => entries,
+            // the directory was removed since its parent was listed
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+          };
+          let mut children: Vec<_> = entries
+            .into_iter()
+            .map(|entry| {
+              if entry.is_dir {
+                Pending::Dir(format!("{dir}{}/", entry.name))
+              } else {
+                Pending::File(format!("{dir}{}", entry.name))
+              }
+            })
+            .filter(|child| self.relevant(child))
+            .collect();
+
+          // directories sort with their trailing `/` so all keys below them
+          // end up in the right place relative to their siblings
+          children.sort_unstable_by(|a, b| b.key().cmp(a.key()));
+          self.pending.extend(children);
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Skips all remaining keys starting with the given prefix
+  pub fn skip_prefix(&mut self, prefix: &str) {
+    // keys sharing a prefix are always on top of the stack together
+    while self
+      .pending
+      .last()
+      .is_some_and(|pending| pending.key().starts_with(prefix))
+    {
+      self.pending.pop();
+    }
+  }
+
+  fn relevant(&self, pending: &Pending) -> bool {
+    match pending {
+      Pending::Dir(dir) => {
+        (dir.starts_with(&self.prefix) || self.prefix.starts_with(dir.as_str()))
+          && self
+            .start_after
+            .as_ref()
+            .is_none_or(|after| dir.as_str() > after.as_str() || after.starts_with(dir.as_str()))
+      }
+      Pending::File(key) => {
+        key.starts_with(&self.prefix)
+          && self
+            .start_after
+            .as_ref()
+            .is_none_or(|after| key.as_str() > after.as_str())
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::Path;
+
+  use super::*;
+  use crate::s3::storage::no_raid::NoRaid;
+
+  async fn storage() -> (NoRaid, PathBuf) {
+    let temp_dir = std::env::temp_dir().join(format!("sagittarius-{}", uuid::Uuid::new_v4()));
+    let storage = NoRaid::new(temp_dir.clone());
+
+    for key in ["a.txt", "a/b", "a/c/d", "a0", "b/e", "c"] {
+      let path = Path::new(key);
+      if let Some(parent) = path.parent() {
+        storage.create_dir(parent).await.unwrap();
+      }
+      storage.write_file(path, b"data").await.unwrap();
+    }
+
+    (storage, temp_dir)
+  }
+
+  async fn collect(walker: &mut KeyWalker<'_>) -> Vec<String> {
+    let mut keys = Vec::new();
+    while let Some(key) = walker.next().await.unwrap() {
+      keys.push(key);
+    }
+    keys
+  }
+
+  #[tokio::test]
+  async fn test_walk_order() {
+    let (storage, temp_dir) = storage().await;
+
+    let mut walker = KeyWalker::new(&storage, PathBuf::new(), "", None);
+    assert_eq!(
+      collect(&mut walker).await,
+      vec!["a.txt", "a/b", "a/c/d", "a0", "b/e", "c"]
+    );
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_walk_prefix_start_after() {
+    let (storage, temp_dir) = storage().await;
+
+    let mut walker = KeyWalker::new(&storage, PathBuf::new(), "a/", None);
+    assert_eq!(collect(&mut walker).await, vec!["a/b", "a/c/d"]);
+
+    let mut walker = KeyWalker::new(&storage, PathBuf::new(), "", Some("a/b"));
+    assert_eq!(collect(&mut walker).await, vec!["a/c/d", "a0", "b/e", "c"]);
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_walk_removed_dir() {
+    let (storage, temp_dir) = storage().await;
+
+    let mut walker = KeyWalker::new(&storage, PathBuf::new(), "", None);
+    assert_eq!(walker.next().await.unwrap(), Some("a.txt".to_string()));
+    storage.delete_dir(Path::new("a")).await.unwrap();
+    assert_eq!(collect(&mut walker).await, vec!["a0", "b/e", "c"]);
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_walk_skip_prefix() {
+    let (storage, temp_dir) = storage().await;
+
+    let mut walker = KeyWalker::new(&storage, PathBuf::new(), "", Some("a.txt"));
+    assert_eq!(walker.next().await.unwrap(), Some("a/b".to_string()));
+    walker.skip_prefix("a/");
+    assert_eq!(collect(&mut walker).await, vec!["a0", "b/e", "c"]);
+
+    tokio::fs::remove_dir_all(temp_dir).await.unwrap();
+  }
+}
@@ -0,0 +1,111 @@
+use axum::{
+  extract::{OriginalUri, Request, State},
+  middleware::Next,
+  response::Response,
+};
+use http::{Uri, header::HOST, uri::PathAndQuery};
+use tracing::instrument;
+
+use crate::config::EnvConfig;
+
+/// Domain under which buckets are addressed as `bucket.domain`
+#[derive(Clone, Debug)]
+pub struct VirtualHost {
+  domain: Option<String>,
+}
+
+impl VirtualHost {
+  pub fn new(config: &EnvConfig) -> Self {
+    Self {
+      domain: config
+        .s3_domain
+        .as_ref()
+        .map(|domain| domain.trim_start_matches('.').to_ascii_lowercase()),
+    }
+  }
+
+  /// Bucket addressed by the host, `None` for path-style requests
+  fn bucket(&self, host: &str) -> Option<String> {
+    let domain = self.domain.as_ref()?;
+    let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    let bucket = host.strip_suffix(domain.as_str())?.strip_suffix('.')?;
+
+    (!bucket.is_empty()).then(|| bucket.to_string())
+  }
+}
+
+/// Rewrites virtual-hosted-style requests onto the path-style routes,
+/// the original uri is kept for the canonical request of signatures
+#[instrument(skip_all)]
+pub async fn virtual_host(
+  State(virtual_host): State<VirtualHost>,
+  mut req: Request,
+  next: Next,
+) -> Response {
+  let host = req
+    .headers()
+    .get(HOST)
+    .and_then(|host| host.to_str().ok())
+    .or_else(|| req.uri().host());
+
+  if let Some(bucket) = host.and_then(|host| virtual_host.bucket(host))
+    && let Some(uri) = bucket_uri(req.uri(), &bucket)
+  {
+    let original = req.uri().clone();
+    req.extensions_mut().insert(OriginalUri(original));
+    *req.uri_mut() = uri;
+  }
+
+  next.run(req).await
+}
+
+fn bucket_uri(uri: &Uri, bucket: &str) -> Option<Uri> {
+  let path = match uri.path() {
+    "/" | "" => format!("/{bucket}"),
+    path => format!("/{bucket}{path}"),
+  };
+  let path_and_query = match uri.query() {
+    Some(query) => format!("{path}?{query}"),
+    None => path,
+  };
+
+  let mut parts = uri.clone().into_parts();
+  parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+  Uri::from_parts(parts).ok()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn virtual_host() -> VirtualHost {
+    VirtualHost::new(&EnvConfig {
+      s3_domain: Some("s3.example.com".into()),
+      ..Default::default()
+    })
+  }
+
+  #[test]
+  fn test_bucket() {
+    let host = virtual_host();
+    assert_eq!(host.bucket("bucket.s3.example.com"), Some("bucket".into()));
+    assert_eq!(
+      host.bucket("my.bucket.S3.example.com:9000"),
+      Some("my.bucket".into())
+    );
+    assert_eq!(host.bucket("s3.example.com"), None);
+    assert_eq!(host.bucket("bucket.other.com"), None);
+    assert_eq!(host.bucket("bucket-s3.example.com"), None);
+
+    let path_style = VirtualHost::new(&EnvConfig::default());
+    assert_eq!(path_style.bucket("bucket.s3.example.com"), None);
+  }
+
+  #[test]
+  fn test_bucket_uri() {
+    let uri = bucket_uri(&"/".parse().unwrap(), "bucket").unwrap();
+    assert_eq!(uri, "/bucket");
+    let uri = bucket_uri(&"/a/b.txt?uploads".parse().unwrap(), "bucket").unwrap();
+    assert_eq!(uri, "/bucket/a/b.txt?uploads");
+  }
+}